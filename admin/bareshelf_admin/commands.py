@@ -1,4 +1,5 @@
 import os
+from typing import Dict, List, Optional, Set
 
 from flask import current_app
 
@@ -14,8 +15,15 @@ def index() -> None:
     index = bareshelf_indexer.create_or_open(path)
 
     print("Indexing ingredients...", flush=True, end="")
-    for ingredient in db.session.query(Ingredient):
+    ingredients = {
+        ingredient.id: ingredient for ingredient in db.session.query(Ingredient)
+    }
+    for ingredient in ingredients.values():
         doc = bareshelf_indexer.Ingredient(ingredient.name, ingredient.slug)
+        for alias in ingredient.aliases:
+            doc.add_alias(alias.name)
+        for tag in _inherited_tags(ingredient, ingredients):
+            doc.add_tag(tag)
         index.add_ingredient(doc)
     print("DONE")
 
@@ -31,7 +39,9 @@ def index() -> None:
         for ingredient in recipe.ingredients:
             if ingredient.ingredient is not None:
                 doc.add_ingredient(
-                    ingredient.ingredient.name, ingredient.ingredient.slug
+                    ingredient.ingredient.name,
+                    ingredient.ingredient.slug,
+                    _inherited_tags(ingredient.ingredient, ingredients),
                 )
 
         index.add_recipe(doc)
@@ -40,3 +50,19 @@ def index() -> None:
     print("Committing...", flush=True, end="")
     index.commit()
     print("DONE")
+
+
+def _inherited_tags(
+    ingredient: Ingredient, ingredients: Dict[int, Ingredient]
+) -> List[str]:
+    """Slugs of the ingredient's tags and the tags of all its parents"""
+    tags: List[str] = []
+    seen: Set[int] = set()
+    current: Optional[Ingredient] = ingredient
+    while current is not None and current.id not in seen:
+        seen.add(current.id)
+        for tag in current.tags:
+            if tag.slug not in tags:
+                tags.append(tag.slug)
+        current = ingredients.get(current.parent_id) if current.parent_id else None
+    return tags
@@ -0,0 +1,55 @@
+"""Helpers for migrations that add tags to ingredients
+
+They are run inside the migration's transaction with alembic's `op`.
+"""
+from typing import List
+
+from alembic import op
+
+
+def add_tag(name: str, slug: str) -> None:
+    op.execute(f"INSERT INTO tags (name, slug) VALUES ('{name}', '{slug}')")
+
+
+def add_ingredients_to_tag(tag_slug: str, ingredient_slugs: List[str]) -> None:
+    tag_id = get_tag_id(tag_slug)
+    ingredient_ids = get_ingredient_ids(ingredient_slugs)
+    op.execute(
+        "INSERT INTO ingredient_tags (tag_id, ingredient_id) VALUES {}".format(
+            ", ".join(
+                f"({tag_id}, {ingredient_id})" for ingredient_id in ingredient_ids
+            )
+        )
+    )
+
+
+def remove_tags(slugs: List[str]) -> None:
+    """Remove tags and untag their ingredients"""
+    tags = ", ".join(f"'{slug}'" for slug in slugs)
+    op.execute(
+        "DELETE FROM ingredient_tags WHERE tag_id IN "
+        f"(SELECT id FROM tags WHERE slug IN ({tags}))"
+    )
+    op.execute(f"DELETE FROM tags WHERE slug IN ({tags})")
+
+
+def get_tag_id(slug: str) -> int:
+    conn = op.get_bind()
+    return conn.execute(f"SELECT id FROM tags WHERE slug='{slug}'").first().id
+
+
+def get_ingredient_ids(slugs: List[str]) -> List[int]:
+    conn = op.get_bind()
+    query = "SELECT id, slug FROM ingredients WHERE slug IN ({})".format(
+        ", ".join(f"'{slug}'" for slug in slugs)
+    )
+
+    ingredients = [ingredient for ingredient in conn.execute(query).fetchall()]
+
+    if len(ingredients) != len(slugs):
+        missing = set(slugs) - set(ingredient.slug for ingredient in ingredients)
+        raise AssertionError(
+            f"not all ingredients found: ({len(ingredients)} != {len(slugs)}) {missing}"
+        )
+
+    return [ingredient.id for ingredient in ingredients]
@@ -60,10 +60,32 @@ class Ingredient(db.Model, WithSlug, WithURL, Timestamps):  # type: ignore
         backref=db.backref("ingredients", lazy=True),
     )
 
+    aliases = db.relationship(
+        "IngredientAlias",
+        back_populates="ingredient",
+        lazy="subquery",
+        cascade="all, delete-orphan",
+    )
+
     def __repr__(self) -> str:
         return f'<Ingredient "{self.name}">'
 
 
+class IngredientAlias(db.Model, Timestamps):  # type: ignore
+    __tablename__ = "ingredient_aliases"
+
+    id = db.Column(db.Integer, primary_key=True, autoincrement=True)
+    ingredient_id = db.Column(
+        db.Integer, db.ForeignKey("ingredients.id"), nullable=False
+    )
+    name = db.Column(db.String, nullable=False)
+
+    ingredient = db.relationship("Ingredient", back_populates="aliases")
+
+    def __repr__(self) -> str:
+        return f'<IngredientAlias "{self.name}">'
+
+
 class Tag(db.Model, WithSlug, Timestamps):  # type: ignore[misc]
     __tablename__ = "tags"
 
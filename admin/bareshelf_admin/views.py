@@ -1,10 +1,11 @@
 from flask_admin.contrib.sqla import ModelView
 
-from .models import RecipeIngredient
+from .models import IngredientAlias, RecipeIngredient
 
 
 class IngredientView(ModelView):  # type: ignore
     form_excluded_columns = ["slug"]
+    inline_models = (IngredientAlias,)
 
 
 class RecipeView(ModelView):  # type: ignore
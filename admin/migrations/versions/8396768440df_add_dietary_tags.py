@@ -0,0 +1,65 @@
+"""Add dietary tags
+
+Tag the ingredients the web app's dietary profiles need to rule out but which aren't
+covered by the initial tags
+
+Revision ID: 8396768440df
+Revises: fc8694aff96f
+Create Date: 2026-10-18 10:12:31.482916
+
+"""
+from alembic import op
+
+from bareshelf_admin.migration_helpers import (
+    add_ingredients_to_tag,
+    add_tag,
+    get_ingredient_ids,
+    get_tag_id,
+    remove_tags,
+)
+
+
+# revision identifiers, used by Alembic.
+revision = "8396768440df"
+down_revision = "fc8694aff96f"
+branch_labels = None
+depends_on = None
+
+DIETARY_TAGS = ["eggs", "honey", "gluten"]
+MEAT_STOCKS = ["chicken-stock", "beef-stock"]
+
+
+def upgrade():
+    add_tag("Eggs", "eggs")
+    add_ingredients_to_tag("eggs", ["egg", "egg-yolk", "egg-white"])
+
+    add_tag("Honey", "honey")
+    add_ingredients_to_tag("honey", ["honey"])
+
+    add_tag("Gluten", "gluten")
+    add_ingredients_to_tag(
+        "gluten",
+        [
+            "flour",
+            "self-raising-flour",
+            "plain-flour",
+            "bread",
+            "breadcrumbs",
+            "puff-pastry",
+            "soy-sauce",
+        ],
+    )
+
+    add_ingredients_to_tag("meat", MEAT_STOCKS)
+
+
+def downgrade():
+    meat_id = get_tag_id("meat")
+    meat_stock_ids = get_ingredient_ids(MEAT_STOCKS)
+    ids = ", ".join(str(ingredient_id) for ingredient_id in meat_stock_ids)
+    op.execute(
+        f"DELETE FROM ingredient_tags WHERE tag_id = {meat_id} "
+        f"AND ingredient_id IN ({ids})"
+    )
+
+    remove_tags(DIETARY_TAGS)
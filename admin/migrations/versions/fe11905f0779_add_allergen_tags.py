@@ -0,0 +1,57 @@
+"""Add allergen tags
+
+Add a tag for each of the 14 major allergens not already covered by a tag, so the
+indexer can work out which allergens a recipe contains. Eggs, fish and gluten have tags
+already and dairy ingredients contain milk.
+
+Revision ID: fe11905f0779
+Revises: 8396768440df
+Create Date: 2026-10-18 11:02:47.913204
+
+"""
+from bareshelf_admin.migration_helpers import (
+    add_ingredients_to_tag,
+    add_tag,
+    remove_tags,
+)
+
+
+# revision identifiers, used by Alembic.
+revision = "fe11905f0779"
+down_revision = "8396768440df"
+branch_labels = None
+depends_on = None
+
+ALLERGEN_TAGS = {
+    "celery": ("Celery", ["celery"]),
+    "crustaceans": ("Crustaceans", ["prawn"]),
+    "lupin": ("Lupin", []),
+    "milk": ("Milk", []),
+    "molluscs": ("Molluscs", []),
+    "mustard": ("Mustard", ["dijon-mustard"]),
+    "tree-nuts": ("Tree nuts", ["ground-almonds", "flaked-almonds", "walnut"]),
+    "peanuts": ("Peanuts", []),
+    "sesame": ("Sesame", ["sesame-seeds", "sesame-oil"]),
+    "soya": ("Soya", ["soy-sauce"]),
+    "sulphites": (
+        "Sulphur dioxide & sulphites",
+        [
+            "white-wine",
+            "red-wine",
+            "white-wine-vinegar",
+            "red-wine-vinegar",
+            "balsamic-vinegar",
+        ],
+    ),
+}
+
+
+def upgrade():
+    for slug, (name, ingredient_slugs) in ALLERGEN_TAGS.items():
+        add_tag(name, slug)
+        if ingredient_slugs:
+            add_ingredients_to_tag(slug, ingredient_slugs)
+
+
+def downgrade():
+    remove_tags(list(ALLERGEN_TAGS))
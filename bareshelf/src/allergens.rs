@@ -0,0 +1,164 @@
+//! The 14 major food allergens
+//!
+//! Which allergens an ingredient contains comes from its tags, see `Allergen::tags`. A recipe
+//! contains the allergens of all its ingredients; they are worked out when the recipe is indexed
+//! and stored as `/allergen/<slug>` facets.
+use serde::{Deserialize, Serialize};
+use tantivy::schema::Facet;
+
+use crate::{datatypes::Ingredient, error::Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Allergen {
+    Celery,
+    Gluten,
+    Crustaceans,
+    Eggs,
+    Fish,
+    Lupin,
+    Milk,
+    Molluscs,
+    Mustard,
+    TreeNuts,
+    Peanuts,
+    Sesame,
+    Soya,
+    Sulphites,
+}
+
+impl Allergen {
+    pub const ALL: [Allergen; 14] = [
+        Allergen::Celery,
+        Allergen::Gluten,
+        Allergen::Crustaceans,
+        Allergen::Eggs,
+        Allergen::Fish,
+        Allergen::Lupin,
+        Allergen::Milk,
+        Allergen::Molluscs,
+        Allergen::Mustard,
+        Allergen::TreeNuts,
+        Allergen::Peanuts,
+        Allergen::Sesame,
+        Allergen::Soya,
+        Allergen::Sulphites,
+    ];
+
+    pub fn slug(&self) -> &'static str {
+        match self {
+            Allergen::Celery => "celery",
+            Allergen::Gluten => "gluten",
+            Allergen::Crustaceans => "crustaceans",
+            Allergen::Eggs => "eggs",
+            Allergen::Fish => "fish",
+            Allergen::Lupin => "lupin",
+            Allergen::Milk => "milk",
+            Allergen::Molluscs => "molluscs",
+            Allergen::Mustard => "mustard",
+            Allergen::TreeNuts => "tree-nuts",
+            Allergen::Peanuts => "peanuts",
+            Allergen::Sesame => "sesame",
+            Allergen::Soya => "soya",
+            Allergen::Sulphites => "sulphites",
+        }
+    }
+
+    /// Short name for the allergen, eg. "gluten" for cereals containing gluten
+    pub fn name(&self) -> &'static str {
+        match self {
+            Allergen::TreeNuts => "tree nuts",
+            allergen => allergen.slug(),
+        }
+    }
+
+    /// Ingredient tags that mean an ingredient contains the allergen
+    ///
+    /// Every allergen has a tag of its own, some also follow from a broader tag.
+    pub fn tags(&self) -> &'static [&'static str] {
+        match self {
+            Allergen::Celery => &["celery"],
+            Allergen::Gluten => &["gluten"],
+            Allergen::Crustaceans => &["crustaceans"],
+            Allergen::Eggs => &["eggs"],
+            Allergen::Fish => &["fish"],
+            Allergen::Lupin => &["lupin"],
+            Allergen::Milk => &["milk", "dairy"],
+            Allergen::Molluscs => &["molluscs"],
+            Allergen::Mustard => &["mustard"],
+            Allergen::TreeNuts => &["tree-nuts"],
+            Allergen::Peanuts => &["peanuts"],
+            Allergen::Sesame => &["sesame"],
+            Allergen::Soya => &["soya"],
+            Allergen::Sulphites => &["sulphites"],
+        }
+    }
+
+    /// The allergens in any of the ingredients, in the order of `Allergen::ALL`
+    pub fn of_ingredients(ingredients: &[Ingredient]) -> Vec<Allergen> {
+        Allergen::ALL
+            .iter()
+            .filter(|allergen| {
+                ingredients.iter().any(|ingredient| {
+                    ingredient
+                        .tags
+                        .iter()
+                        .any(|tag| allergen.tags().contains(&tag.as_str()))
+                })
+            })
+            .copied()
+            .collect()
+    }
+
+    pub(crate) fn facet(&self) -> Facet {
+        Facet::from(&format!("/allergen/{}", self.slug()))
+    }
+
+    pub(crate) fn from_facet(facet: &Facet) -> Option<Allergen> {
+        let path = facet.to_path();
+        Allergen::ALL
+            .iter()
+            .find(|allergen| path == ["allergen", allergen.slug()])
+            .copied()
+    }
+}
+
+impl std::str::FromStr for Allergen {
+    type Err = Error;
+
+    fn from_str(slug: &str) -> Result<Self, Self::Err> {
+        Allergen::ALL
+            .iter()
+            .find(|allergen| allergen.slug() == slug)
+            .copied()
+            .ok_or_else(|| Error::Other(format!("unknown allergen {}", slug)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allergens_come_from_ingredient_tags() {
+        let ingredients = vec![
+            Ingredient::new("Butter", "butter").with_tags(&["dairy"]),
+            Ingredient::new("Flour", "flour").with_tags(&["basics", "gluten"]),
+            Ingredient::new("Egg", "egg").with_tags(&["eggs"]),
+            Ingredient::new("Sugar", "sugar"),
+        ];
+
+        assert_eq!(
+            Allergen::of_ingredients(&ingredients),
+            vec![Allergen::Gluten, Allergen::Eggs, Allergen::Milk]
+        );
+    }
+
+    #[test]
+    fn allergen_facets_round_trip() {
+        for allergen in Allergen::ALL {
+            assert_eq!(Allergen::from_facet(&allergen.facet()), Some(allergen));
+            assert_eq!(allergen.slug().parse::<Allergen>().unwrap(), allergen);
+        }
+    }
+}
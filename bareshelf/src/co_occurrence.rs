@@ -0,0 +1,109 @@
+//! Co-occurrence collector
+//!
+//! Counts the ingredients appearing in recipes alongside the ingredients on a shelf. Each recipe
+//! can be weighted by how much of it the shelf covers, so ingredients in recipes that are nearly
+//! makeable count for more than those in recipes sharing a single ingredient with the shelf.
+use std::collections::{HashMap, HashSet};
+use tantivy::schema::{Facet, Field};
+
+use crate::shelf_collector::{ShelfCollector, ShelfTally};
+
+/// Number of recipes and their total weight for each ingredient not on the shelf
+pub(crate) type CoOccurrenceCollector = ShelfCollector<CoOccurrence>;
+
+#[derive(Clone)]
+pub(crate) struct CoOccurrence {
+    weighted: bool,
+}
+
+impl CoOccurrenceCollector {
+    pub fn new(field: Field, shelf: Vec<Facet>, weighted: bool) -> Self {
+        Self::with_tally(field, shelf, CoOccurrence { weighted })
+    }
+}
+
+impl ShelfTally for CoOccurrence {
+    type Count = (u64, f32);
+
+    fn collect(
+        &self,
+        facet_ords: &[u64],
+        shelf: &HashSet<u64>,
+        counts: &mut HashMap<u64, (u64, f32)>,
+    ) {
+        if facet_ords.is_empty() {
+            return;
+        }
+        let weight = if self.weighted {
+            let on_shelf = facet_ords.iter().filter(|o| shelf.contains(o)).count();
+            on_shelf as f32 / facet_ords.len() as f32
+        } else {
+            1.0
+        };
+        for ord in facet_ords.iter() {
+            if !shelf.contains(ord) {
+                let entry = counts.entry(*ord).or_insert((0, 0.0));
+                entry.0 += 1;
+                entry.1 += weight;
+            }
+        }
+    }
+
+    fn merge(total: &mut (u64, f32), (count, weight): (u64, f32)) {
+        total.0 += count;
+        total.1 += weight;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shelf_collector::tests::index_recipes;
+    use tantivy::query::AllQuery;
+
+    #[test]
+    fn co_occurrence_collector() {
+        let (index, ingredient) = index_recipes(&[
+            ("Scrambled Egg", &["egg", "butter", "salt", "pepper"]),
+            ("Jam on Toast", &["bread", "butter", "jam", "salt"]),
+        ]);
+
+        let reader = index.reader().unwrap();
+        let searcher = reader.searcher();
+        let shelf = vec![
+            Facet::from("/ingredient/egg"),
+            Facet::from("/ingredient/butter"),
+            Facet::from("/ingredient/pepper"),
+        ];
+
+        let counts = |weighted| {
+            let mut counts: Vec<(Facet, (u64, f32))> = searcher
+                .search(
+                    &AllQuery,
+                    &CoOccurrenceCollector::new(ingredient, shelf.clone(), weighted),
+                )
+                .unwrap()
+                .into_iter()
+                .collect();
+            counts.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            counts
+        };
+
+        assert_eq!(
+            counts(false),
+            vec![
+                (Facet::from("/ingredient/bread"), (1, 1.0)),
+                (Facet::from("/ingredient/jam"), (1, 1.0)),
+                (Facet::from("/ingredient/salt"), (2, 2.0)),
+            ]
+        );
+        assert_eq!(
+            counts(true),
+            vec![
+                (Facet::from("/ingredient/bread"), (1, 0.25)),
+                (Facet::from("/ingredient/jam"), (1, 0.25)),
+                (Facet::from("/ingredient/salt"), (2, 1.0)),
+            ]
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
 
 use serde::{Deserialize, Serialize};
 use tantivy::{
@@ -6,6 +9,8 @@ use tantivy::{
     Document,
 };
 
+use crate::allergens::Allergen;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Recipe {
     pub title: String,
@@ -14,6 +19,12 @@ pub struct Recipe {
     pub chef_name: Option<String>,
     pub image_name: Option<String>,
     pub ingredients: Vec<Ingredient>,
+    /// Allergens in the ingredients
+    ///
+    /// Worked out from the ingredients' tags when indexing, so only filled in for recipes loaded
+    /// from the index.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allergens: Vec<Allergen>,
 }
 
 impl Recipe {
@@ -25,9 +36,15 @@ impl Recipe {
             chef_name: None,
             image_name: None,
             ingredients,
+            allergens: vec![],
         }
     }
 
+    /// Host name of the site the recipe is from, eg. "www.bbcgoodfood.com"
+    pub fn host(&self) -> Option<String> {
+        Some(url::Url::parse(&self.url).ok()?.host_str()?.to_owned())
+    }
+
     pub(crate) fn from_doc(schema: &Schema, doc: &Document) -> Option<Self> {
         Some(Self {
             title: get_first_text(doc, get_field(schema, "title"))?,
@@ -44,6 +61,15 @@ impl Recipe {
                         Value::Facet(value) => IngredientSlug::from(value).into(),
                         _ => unreachable!(),
                     },
+                    aliases: vec![],
+                    tags: vec![],
+                })
+                .collect(),
+            allergens: doc
+                .get_all(get_field(schema, "allergen"))
+                .filter_map(|allergen| match allergen {
+                    Value::Facet(facet) => Allergen::from_facet(facet),
+                    _ => None,
                 })
                 .collect(),
         })
@@ -65,10 +91,20 @@ fn get_first_text(doc: &Document, field: Field) -> Option<String> {
     )
 }
 
-#[derive(Hash, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// An ingredient
+///
+/// Ingredients are identified by their slug; two ingredients with the same slug
+/// are equal regardless of their name or aliases.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ingredient {
     pub name: String,
     pub slug: String,
+    /// Alternate names for the ingredient, eg. "eggplant" for "Aubergine"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Slugs of the tags the ingredient has, including those inherited from its parents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 impl Ingredient {
@@ -76,12 +112,43 @@ impl Ingredient {
         Self {
             name: String::from(name),
             slug: String::from(slug),
+            aliases: vec![],
+            tags: vec![],
         }
     }
 
+    pub fn with_aliases(mut self, aliases: &[&str]) -> Self {
+        self.aliases = aliases.iter().map(|alias| String::from(*alias)).collect();
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|tag| String::from(*tag)).collect();
+        self
+    }
+
     pub fn slug(&self) -> String {
         self.slug.clone()
     }
+
+    /// All the names this ingredient is known by, starting with the canonical name
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+impl PartialEq for Ingredient {
+    fn eq(&self, other: &Ingredient) -> bool {
+        self.slug == other.slug
+    }
+}
+
+impl Eq for Ingredient {}
+
+impl Hash for Ingredient {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.slug.hash(state);
+    }
 }
 
 impl PartialOrd for Ingredient {
@@ -96,7 +163,7 @@ impl Ord for Ingredient {
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct IngredientSlug(String);
 
 impl IngredientSlug {
@@ -180,4 +247,21 @@ mod tests {
         let facet: Facet = slug.into();
         assert_eq!(facet, Facet::from("/ingredient/recipe"));
     }
+
+    #[test]
+    fn ingredients_are_equal_by_slug() {
+        let aubergine = Ingredient::new("Aubergine", "aubergine");
+        let with_aliases = Ingredient::new("Aubergine", "aubergine").with_aliases(&["eggplant"]);
+
+        assert_eq!(aubergine, with_aliases);
+        assert_ne!(aubergine, Ingredient::new("Aubergine", "courgette"));
+    }
+
+    #[test]
+    fn ingredient_without_aliases_deserializes() {
+        let ingredient: Ingredient =
+            serde_json::from_str(r#"{"name": "Aubergine", "slug": "aubergine"}"#).unwrap();
+
+        assert!(ingredient.aliases.is_empty());
+    }
 }
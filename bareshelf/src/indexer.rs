@@ -1,4 +1,7 @@
+use tantivy::schema::Facet;
+
 use crate::{
+    allergens::Allergen,
     datatypes::{Ingredient, Recipe},
     error::Result,
     ingredients_schema, recipes_schema,
@@ -10,13 +13,18 @@ pub struct Indexer {
     recipes_slug: tantivy::schema::Field,
     recipes_url: tantivy::schema::Field,
     recipes_chef_name: tantivy::schema::Field,
+    recipes_chef: tantivy::schema::Field,
+    recipes_host: tantivy::schema::Field,
     recipes_image_name: tantivy::schema::Field,
     recipes_ingredient_name: tantivy::schema::Field,
     recipes_ingredient_slug: tantivy::schema::Field,
+    recipes_allergen: tantivy::schema::Field,
 
     ingredients_writer: tantivy::IndexWriter,
     ingredients_name: tantivy::schema::Field,
     ingredients_slug: tantivy::schema::Field,
+    ingredients_aliases: tantivy::schema::Field,
+    ingredients_tags: tantivy::schema::Field,
 }
 
 impl Indexer {
@@ -29,13 +37,18 @@ impl Indexer {
             recipes_slug: recipes_schema.get_field("slug").unwrap(),
             recipes_url: recipes_schema.get_field("url").unwrap(),
             recipes_chef_name: recipes_schema.get_field("chef_name").unwrap(),
+            recipes_chef: recipes_schema.get_field("chef").unwrap(),
+            recipes_host: recipes_schema.get_field("host").unwrap(),
             recipes_image_name: recipes_schema.get_field("image_name").unwrap(),
             recipes_ingredient_name: recipes_schema.get_field("ingredient_name").unwrap(),
             recipes_ingredient_slug: recipes_schema.get_field("ingredient_slug").unwrap(),
+            recipes_allergen: recipes_schema.get_field("allergen").unwrap(),
 
             ingredients_writer: ingredients.writer(30_000_000)?,
             ingredients_name: ingredients_schema.get_field("name").unwrap(),
             ingredients_slug: ingredients_schema.get_field("slug").unwrap(),
+            ingredients_aliases: ingredients_schema.get_field("aliases").unwrap(),
+            ingredients_tags: ingredients_schema.get_field("tags").unwrap(),
         })
     }
 
@@ -58,6 +71,10 @@ impl Indexer {
         document.add_text(self.recipes_url, &recipe.url);
         if let Some(chef_name) = &recipe.chef_name {
             document.add_text(self.recipes_chef_name, chef_name);
+            document.add_facet(self.recipes_chef, Facet::from_path(["chef", chef_name]));
+        }
+        if let Some(host) = recipe.host() {
+            document.add_facet(self.recipes_host, Facet::from_path(["host", &host]));
         }
         if let Some(image_name) = &recipe.image_name {
             document.add_text(self.recipes_image_name, image_name);
@@ -70,6 +87,9 @@ impl Indexer {
                 &format!("/ingredient/{}", ingredient.slug),
             );
         });
+        Allergen::of_ingredients(&recipe.ingredients)
+            .iter()
+            .for_each(|allergen| document.add_facet(self.recipes_allergen, allergen.facet()));
         document
     }
 
@@ -83,6 +103,12 @@ impl Indexer {
         let mut document = tantivy::schema::Document::default();
         document.add_text(self.ingredients_name, &ingredient.name);
         document.add_text(self.ingredients_slug, &ingredient.slug);
+        ingredient.aliases.iter().for_each(|alias| {
+            document.add_text(self.ingredients_aliases, alias);
+        });
+        ingredient.tags.iter().for_each(|tag| {
+            document.add_text(self.ingredients_tags, tag);
+        });
         document
     }
 }
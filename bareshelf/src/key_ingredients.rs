@@ -0,0 +1,140 @@
+//! How many key ingredients a recipe needs
+//!
+//! `Any` and `All` are plain boolean queries. Tantivy can't require a minimum number of `Should`
+//! clauses, so for `AtLeast` the query only asks for one key ingredient and the collectors are
+//! wrapped in a `KeyIngredientFilter`, which drops recipes with too few of them.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::FacetReader,
+    schema::{Facet, Field},
+    DocId, Result, Score, SegmentOrdinal, SegmentReader,
+};
+
+use crate::shelf_collector::shelf_facet_reader;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyIngredientMode {
+    /// Recipes need one of the key ingredients
+    #[default]
+    Any,
+    /// Recipes need every key ingredient
+    All,
+    /// Recipes need this many of the key ingredients, or all of them if there are fewer
+    AtLeast(usize),
+}
+
+impl KeyIngredientMode {
+    /// How many of `count` key ingredients a recipe needs
+    ///
+    /// A recipe always needs at least one key ingredient when there are any.
+    pub fn required(&self, count: usize) -> usize {
+        match self {
+            KeyIngredientMode::Any => count.min(1),
+            KeyIngredientMode::All => count,
+            KeyIngredientMode::AtLeast(n) => (*n).clamp(count.min(1), count),
+        }
+    }
+}
+
+/// Only passes recipes with at least `required` key ingredients on to the wrapped collector
+pub(crate) struct KeyIngredientFilter<C> {
+    field: Field,
+    key_ingredients: Vec<Facet>,
+    required: usize,
+    collector: C,
+}
+
+pub(crate) struct KeyIngredientSegmentFilter<C> {
+    reader: FacetReader,
+    key_ingredients: HashSet<u64>,
+    required: usize,
+    facet_ords_buf: Vec<u64>,
+    collector: C,
+}
+
+impl<C> KeyIngredientFilter<C> {
+    pub fn new(field: Field, key_ingredients: Vec<Facet>, required: usize, collector: C) -> Self {
+        Self {
+            field,
+            key_ingredients,
+            required,
+            collector,
+        }
+    }
+}
+
+impl<C: Collector> Collector for KeyIngredientFilter<C> {
+    type Fruit = C::Fruit;
+    type Child = KeyIngredientSegmentFilter<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let (facet_reader, key_ingredients) =
+            shelf_facet_reader(reader, self.field, &self.key_ingredients)?;
+
+        Ok(KeyIngredientSegmentFilter {
+            reader: facet_reader,
+            key_ingredients,
+            required: self.required,
+            facet_ords_buf: Vec::with_capacity(255),
+            collector: self.collector.for_segment(segment_local_id, reader)?,
+        })
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> Result<Self::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+}
+
+impl<C: SegmentCollector> SegmentCollector for KeyIngredientSegmentFilter<C> {
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        // a single key ingredient is already required by the query
+        if self.required > 1 {
+            self.reader.facet_ords(doc, &mut self.facet_ords_buf);
+            let key_ingredients = self
+                .facet_ords_buf
+                .iter()
+                .filter(|o| self.key_ingredients.contains(o))
+                .count();
+            if key_ingredients < self.required {
+                return;
+            }
+        }
+        self.collector.collect(doc, score);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.harvest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn required_key_ingredients() {
+        assert_eq!(KeyIngredientMode::Any.required(3), 1);
+        assert_eq!(KeyIngredientMode::All.required(3), 3);
+        assert_eq!(KeyIngredientMode::AtLeast(2).required(3), 2);
+        assert_eq!(KeyIngredientMode::AtLeast(5).required(3), 3);
+        assert_eq!(KeyIngredientMode::AtLeast(0).required(3), 1);
+        assert_eq!(KeyIngredientMode::All.required(0), 0);
+    }
+}
@@ -1,17 +1,32 @@
 use std::path::Path;
-use tantivy::schema::{STORED, STRING, TEXT};
 
+use log::warn;
+use tantivy::schema::{IndexRecordOption, TextFieldIndexing, TextOptions, STORED, STRING};
+
+mod allergens;
+mod co_occurrence;
 mod datatypes;
 mod error;
 mod indexer;
+mod key_ingredients;
 mod next_ingredient;
+mod planner;
+mod resolver;
 mod searcher;
+mod shelf_collector;
 
 pub use crate::{
+    allergens::Allergen,
     datatypes::{Ingredient, IngredientSlug, Recipe},
     error::{Error, Result},
     indexer::Indexer,
-    searcher::{IngredientQuery, RecipeQuery, RecipeSearchResult, Searcher},
+    key_ingredients::KeyIngredientMode,
+    planner::MealPlan,
+    resolver::{Confidence, IngredientResolver, Resolution},
+    searcher::{
+        IngredientPairing, IngredientQuery, Popularity, RecipeFacetCounts, RecipeQuery,
+        RecipeSearchResult, Searcher,
+    },
 };
 
 pub fn indexer(path: &Path) -> Result<Indexer> {
@@ -23,17 +38,39 @@ pub fn indexer(path: &Path) -> Result<Indexer> {
 
 pub fn searcher(path: &Path) -> Result<Searcher> {
     Searcher::new(
-        &open_index(path.join("recipes").as_path())?,
-        &open_index(path.join("ingredients").as_path())?,
+        &open_index(path.join("recipes").as_path(), recipes_schema())?,
+        &open_index(path.join("ingredients").as_path(), ingredients_schema())?,
     )
 }
 
-fn open_index(path: &Path) -> Result<tantivy::Index> {
+/// Open an index, failing if it was built with a different schema
+fn open_index(path: &Path, schema: tantivy::schema::Schema) -> Result<tantivy::Index> {
     let directory = tantivy::directory::MmapDirectory::open(path)?;
-    Ok(tantivy::Index::open(directory)?)
+    let index = tantivy::Index::open(directory)?;
+    if index.schema() != schema {
+        return Err(Error::Other(format!(
+            "{} was built with a different schema, it needs to be indexed again",
+            path.display()
+        )));
+    }
+    Ok(index)
 }
 
+/// Open an index, or create it if it is missing or was built with a different schema
+///
+/// The index is always built again from scratch, so an index from before a schema change is
+/// thrown away rather than migrated.
 fn open_or_create_index(path: &Path, schema: tantivy::schema::Schema) -> Result<tantivy::Index> {
+    if path.join("meta.json").exists() {
+        let directory = tantivy::directory::MmapDirectory::open(path)?;
+        if tantivy::Index::open(directory)?.schema() != schema {
+            warn!(
+                "{} was built with a different schema, recreating it",
+                path.display()
+            );
+            std::fs::remove_dir_all(path)?;
+        }
+    }
     if !path.exists() {
         std::fs::create_dir_all(path)?;
     }
@@ -41,22 +78,40 @@ fn open_or_create_index(path: &Path, schema: tantivy::schema::Schema) -> Result<
     Ok(tantivy::Index::open_or_create(directory, schema)?)
 }
 
+/// English stemming tokenizer, registered by tantivy on every index
+///
+/// Used for ingredient names so that "eggs" matches "egg" and "tomatoes" matches "tomato".
+const STEMMED_TOKENIZER: &str = "en_stem";
+
+fn stemmed_text() -> TextOptions {
+    TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(STEMMED_TOKENIZER)
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+    )
+}
+
 fn recipes_schema() -> tantivy::schema::Schema {
     let mut schema_builder = tantivy::schema::Schema::builder();
-    schema_builder.add_text_field("title", TEXT | STORED);
+    schema_builder.add_text_field("title", stemmed_text() | STORED);
     schema_builder.add_text_field("slug", STRING | STORED);
     schema_builder.add_text_field("url", STORED);
     schema_builder.add_text_field("image_name", STORED);
     schema_builder.add_text_field("chef_name", STORED);
-    schema_builder.add_facet_field("ingredient_slug", ());
-    schema_builder.add_text_field("ingredient_name", TEXT | STORED);
+    schema_builder.add_facet_field("chef", ());
+    schema_builder.add_facet_field("host", ());
+    schema_builder.add_facet_field("ingredient_slug", STORED);
+    schema_builder.add_text_field("ingredient_name", stemmed_text() | STORED);
+    schema_builder.add_facet_field("allergen", STORED);
     schema_builder.build()
 }
 
 fn ingredients_schema() -> tantivy::schema::Schema {
     let mut schema_builder = tantivy::schema::Schema::builder();
-    schema_builder.add_text_field("name", TEXT | STORED);
+    schema_builder.add_text_field("name", stemmed_text() | STORED);
     schema_builder.add_text_field("slug", STRING | STORED);
+    schema_builder.add_text_field("aliases", stemmed_text() | STORED);
+    schema_builder.add_text_field("tags", STRING | STORED);
     schema_builder.build()
 }
 
@@ -97,7 +152,7 @@ pub(crate) mod tests {
     }
 
     fn index_recipes(recipes_index: &tantivy::Index, ingredients_index: &tantivy::Index) {
-        let mut indexer = Indexer::new(&recipes_index, &ingredients_index).unwrap();
+        let mut indexer = Indexer::new(recipes_index, ingredients_index).unwrap();
 
         indexer.add_recipe(Recipe::new(
             "Fried egg",
@@ -111,41 +166,68 @@ pub(crate) mod tests {
             "http://example.org/two",
             vec![
                 Ingredient::new("Egg", "egg"),
-                Ingredient::new("Butter", "butter"),
-                Ingredient::new("Milk", "milk"),
+                Ingredient::new("Butter", "butter").with_tags(&["dairy"]),
+                Ingredient::new("Milk", "milk").with_tags(&["dairy"]),
                 Ingredient::new("Salt", "salt"),
             ],
         ));
-        indexer.add_recipe(Recipe::new(
-            "Egg rolls",
-            "egg-rolls",
-            "http://example.org/three",
-            vec![
-                Ingredient::new("Egg", "egg"),
-                Ingredient::new("Garlic", "garlic"),
-                Ingredient::new("Salt", "salt"),
-                Ingredient::new("Oil", "oil"),
-                Ingredient::new("Tortilla wrap", "tortilla-wrap"),
-                Ingredient::new("Mushroom", "mushroom"),
-            ],
-        ));
+        indexer.add_recipe(Recipe {
+            chef_name: Some("Nigel Slater".to_string()),
+            ..Recipe::new(
+                "Egg rolls",
+                "egg-rolls",
+                "http://example.com/three",
+                vec![
+                    Ingredient::new("Egg", "egg"),
+                    Ingredient::new("Garlic", "garlic"),
+                    Ingredient::new("Salt", "salt"),
+                    Ingredient::new("Oil", "oil"),
+                    Ingredient::new("Tortilla wrap", "tortilla-wrap"),
+                    Ingredient::new("Mushroom", "mushroom"),
+                ],
+            )
+        });
         indexer.commit().unwrap();
     }
 
     fn index_ingredients(recipes_index: &tantivy::Index, ingredients_index: &tantivy::Index) {
-        let mut indexer = Indexer::new(&recipes_index, &ingredients_index).unwrap();
-        indexer.add_ingredient(Ingredient::new("Peanut butter", "peanut-butter"));
+        let mut indexer = Indexer::new(recipes_index, ingredients_index).unwrap();
+        indexer.add_ingredient(
+            Ingredient::new("Peanut butter", "peanut-butter").with_tags(&["nuts-seeds"]),
+        );
         indexer.add_ingredient(Ingredient::new("Sugar", "sugar"));
         indexer.add_ingredient(Ingredient::new("Egg", "egg"));
-        indexer.add_ingredient(Ingredient::new("Butter", "butter"));
+        indexer.add_ingredient(Ingredient::new("Butter", "butter").with_tags(&["dairy"]));
         indexer.add_ingredient(Ingredient::new("Butter beans", "butter-beans"));
         indexer.add_ingredient(Ingredient::new("Brown sugar", "brown-sugar"));
         indexer.add_ingredient(Ingredient::new("Garlic", "garlic"));
-        indexer.add_ingredient(Ingredient::new("Milk", "milk"));
+        indexer.add_ingredient(Ingredient::new("Milk", "milk").with_tags(&["dairy"]));
         indexer.add_ingredient(Ingredient::new("Salt", "salt"));
         indexer.add_ingredient(Ingredient::new("Oil", "oil"));
         indexer.add_ingredient(Ingredient::new("Tortilla wrap", "tortilla-wrap"));
         indexer.add_ingredient(Ingredient::new("Mushroom", "mushroom"));
+        indexer.add_ingredient(Ingredient::new("Tomatoes", "tomatoes"));
+        indexer.add_ingredient(Ingredient::new("Cherry tomato", "cherry-tomato"));
+        indexer
+            .add_ingredient(Ingredient::new("Aubergine", "aubergine").with_aliases(&["Eggplant"]));
+        indexer.add_ingredient(
+            Ingredient::new("Spring onion", "spring-onion")
+                .with_aliases(&["Scallion", "Green onion"]),
+        );
         indexer.commit().unwrap();
     }
+
+    #[test]
+    fn index_with_a_different_schema_is_recreated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        let mut schema_builder = tantivy::schema::Schema::builder();
+        schema_builder.add_text_field("title", STORED);
+        open_or_create_index(&path, schema_builder.build()).unwrap();
+        assert!(open_index(&path, recipes_schema()).is_err());
+
+        let index = open_or_create_index(&path, recipes_schema()).unwrap();
+        assert_eq!(index.schema(), recipes_schema());
+        assert!(open_index(&path, recipes_schema()).is_ok());
+    }
 }
@@ -24,6 +24,11 @@ enum Command {
     IngredientsByPrefix {
         prefix: String,
     },
+    Pairings {
+        #[structopt(short, long, default_value = "20")]
+        limit: usize,
+        slug: String,
+    },
 }
 
 fn main() -> Result<()> {
@@ -71,6 +76,14 @@ fn main() -> Result<()> {
                     println!("{:?}", ingredient);
                 });
         }
+        Command::Pairings { limit, slug } => {
+            for pairing in searcher.ingredient_pairings(&slug, limit)? {
+                println!(
+                    "{} {} {:.2}",
+                    pairing.ingredient.slug, pairing.recipes, pairing.lift
+                );
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,156 @@
+//! Meal planner
+//!
+//! Picks a recipe for each of a number of days from recipe search results. It is greedy: each
+//! day gets the recipe that adds the fewest ingredients to the shopping list, preferring recipes
+//! that reuse ingredients of the days already planned.
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{searcher::RecipeSearchResult, Ingredient};
+
+/// Recipes for consecutive days
+pub struct MealPlan {
+    recipes: Vec<RecipeSearchResult>,
+}
+
+impl MealPlan {
+    pub(crate) fn new(mut candidates: Vec<RecipeSearchResult>, days: usize) -> Self {
+        let mut recipes = vec![];
+        let mut to_buy: HashSet<String> = HashSet::new();
+        let mut used: HashSet<String> = HashSet::new();
+
+        while recipes.len() < days && !candidates.is_empty() {
+            let new_missing = |recipe: &RecipeSearchResult| {
+                recipe
+                    .missing_ingredients
+                    .iter()
+                    .filter(|slug| !to_buy.contains(*slug))
+                    .count()
+            };
+            let reused = |recipe: &RecipeSearchResult| {
+                recipe
+                    .recipe
+                    .ingredients
+                    .iter()
+                    .filter(|ingredient| used.contains(&ingredient.slug))
+                    .count()
+            };
+            let (best, _) = candidates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    new_missing(a)
+                        .cmp(&new_missing(b))
+                        .then_with(|| reused(b).cmp(&reused(a)))
+                        .then_with(|| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal))
+                })
+                .unwrap();
+
+            let recipe = candidates.remove(best);
+            to_buy.extend(recipe.missing_ingredients.iter().cloned());
+            used.extend(
+                recipe
+                    .recipe
+                    .ingredients
+                    .iter()
+                    .map(|ingredient| ingredient.slug.clone()),
+            );
+            recipes.push(recipe);
+        }
+
+        Self { recipes }
+    }
+
+    /// The recipe for each day, in order
+    ///
+    /// There are fewer recipes than days if the search did not find enough.
+    pub fn recipes(&self) -> &[RecipeSearchResult] {
+        &self.recipes
+    }
+
+    /// Ingredients missing from the shelf with how many of the planned recipes need them
+    ///
+    /// Ingredients are in the order they are first needed.
+    pub fn shopping_list(&self) -> Vec<(Ingredient, usize)> {
+        let mut list: Vec<(Ingredient, usize)> = vec![];
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        for recipe in self.recipes.iter() {
+            for ingredient in recipe.recipe.ingredients.iter() {
+                if !recipe.missing_ingredients.contains(&ingredient.slug) {
+                    continue;
+                }
+                match positions.get(ingredient.slug.as_str()) {
+                    Some(position) => list[*position].1 += 1,
+                    None => {
+                        positions.insert(&ingredient.slug, list.len());
+                        list.push((ingredient.clone(), 1));
+                    }
+                }
+            }
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Recipe;
+
+    fn result(title: &str, score: f32, ingredients: &[&str], shelf: &[&str]) -> RecipeSearchResult {
+        RecipeSearchResult {
+            score,
+            recipe: Recipe::new(
+                title,
+                title,
+                "http://example.org",
+                ingredients
+                    .iter()
+                    .map(|slug| Ingredient::new(slug, slug))
+                    .collect(),
+            ),
+            missing_ingredients: ingredients
+                .iter()
+                .filter(|slug| !shelf.contains(slug))
+                .map(|slug| slug.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn plan_fewest_new_ingredients_then_most_reused() {
+        let shelf = ["egg"];
+        let candidates = vec![
+            result("omelette", 3.0, &["egg", "milk", "cheese"], &shelf),
+            result("pancakes", 2.0, &["egg", "milk", "flour"], &shelf),
+            result("cheese-toast", 1.0, &["bread", "cheese"], &shelf),
+            result("fried-egg", 0.5, &["egg", "oil"], &shelf),
+        ];
+
+        let plan = MealPlan::new(candidates, 3);
+
+        assert_eq!(
+            plan.recipes()
+                .iter()
+                .map(|r| r.recipe.slug.as_str())
+                .collect::<Vec<_>>(),
+            vec!["fried-egg", "omelette", "pancakes"]
+        );
+        assert_eq!(
+            plan.shopping_list()
+                .iter()
+                .map(|(ingredient, count)| (ingredient.slug.as_str(), *count))
+                .collect::<Vec<_>>(),
+            vec![("oil", 1), ("milk", 2), ("cheese", 1), ("flour", 1)]
+        );
+    }
+
+    #[test]
+    fn plan_is_short_when_there_are_too_few_recipes() {
+        let plan = MealPlan::new(vec![result("toast", 1.0, &["bread"], &[])], 7);
+
+        assert_eq!(plan.recipes().len(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+//! Ingredient resolver
+//!
+//! Turns free-form user input into an ingredient. The input may be a slug, a name, an alias or
+//! just the start of a name so rather than guessing the resolver reports how sure it is.
+use std::collections::HashSet;
+
+use crate::{
+    datatypes::{Ingredient, IngredientSlug},
+    error::Result,
+    searcher::{IngredientQuery, Searcher},
+};
+
+/// How sure the resolver is that it found what the user meant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Several ingredients could have been meant
+    Low,
+    /// Only one ingredient could have been meant but the input did not name it
+    Medium,
+    /// The input was the slug, name or alias of the ingredient
+    High,
+}
+
+#[derive(Debug)]
+pub enum Resolution {
+    Exact(Ingredient),
+    Candidates {
+        candidates: Vec<Ingredient>,
+        confidence: Confidence,
+    },
+    NotFound,
+}
+
+impl Resolution {
+    pub fn confidence(&self) -> Option<Confidence> {
+        match self {
+            Resolution::Exact(_) => Some(Confidence::High),
+            Resolution::Candidates { confidence, .. } => Some(*confidence),
+            Resolution::NotFound => None,
+        }
+    }
+}
+
+pub struct IngredientResolver<'a> {
+    searcher: &'a Searcher,
+    excluding: Vec<Ingredient>,
+    limit: usize,
+}
+
+impl<'a> IngredientResolver<'a> {
+    pub fn new(searcher: &'a Searcher) -> Self {
+        Self {
+            searcher,
+            excluding: vec![],
+            limit: 10,
+        }
+    }
+
+    /// Set the maximum number of candidates to return
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Ingredients that should not be offered as candidates
+    ///
+    /// Exact matches are still returned so that callers can tell the user they already have it.
+    pub fn excluding(mut self, excluding: &[Ingredient]) -> Self {
+        self.excluding = excluding.to_vec();
+        self
+    }
+
+    pub fn resolve(&self, input: &str) -> Result<Resolution> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(Resolution::NotFound);
+        }
+
+        let slugs = [IngredientSlug::from(input)];
+        if let Some(ingredient) = self
+            .searcher
+            .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(1))?
+            .pop()
+        {
+            return Ok(Resolution::Exact(ingredient));
+        }
+
+        let mut by_name = self
+            .searcher
+            .ingredients(IngredientQuery::by_name(input).with_limit(self.limit))?;
+        match by_name.len() {
+            0 => {}
+            1 => return Ok(Resolution::Exact(by_name.remove(0))),
+            _ => return Ok(self.candidates(by_name, Confidence::Low)),
+        }
+
+        let by_prefix = self.searcher.ingredients(
+            IngredientQuery::by_prefix(input)
+                .excluding(&self.excluding)
+                .with_limit(self.limit),
+        )?;
+        let confidence = if by_prefix.len() == 1 {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        };
+        Ok(self.candidates(by_prefix, confidence))
+    }
+
+    fn candidates(&self, candidates: Vec<Ingredient>, confidence: Confidence) -> Resolution {
+        let excluding: HashSet<_> = self.excluding.iter().collect();
+        let candidates: Vec<_> = candidates
+            .into_iter()
+            .filter(|ingredient| !excluding.contains(ingredient))
+            .collect();
+
+        if candidates.is_empty() {
+            Resolution::NotFound
+        } else {
+            Resolution::Candidates {
+                candidates,
+                confidence,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_ingredients_index;
+
+    fn searcher() -> Searcher {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+        Searcher::new(&recipes_index, &ingredients_index).unwrap()
+    }
+
+    fn names(resolution: &Resolution) -> Vec<&str> {
+        match resolution {
+            Resolution::Exact(ingredient) => vec![ingredient.name.as_str()],
+            Resolution::Candidates { candidates, .. } => {
+                candidates.iter().map(|i| i.name.as_str()).collect()
+            }
+            Resolution::NotFound => vec![],
+        }
+    }
+
+    #[test]
+    fn resolve_slug_name_and_alias() {
+        let searcher = searcher();
+        let resolver = IngredientResolver::new(&searcher);
+
+        for input in &["spring-onion", "Spring onions", "scallion"] {
+            let resolution = resolver.resolve(input).unwrap();
+            assert!(matches!(resolution, Resolution::Exact(_)), "{}", input);
+            assert_eq!(names(&resolution), vec!["Spring onion"]);
+        }
+    }
+
+    #[test]
+    fn resolve_prefix_to_candidates() {
+        let searcher = searcher();
+        let resolver = IngredientResolver::new(&searcher);
+
+        let resolution = resolver.resolve("butt").unwrap();
+        assert_eq!(resolution.confidence(), Some(Confidence::Low));
+        assert_eq!(
+            names(&resolution),
+            vec!["Butter", "Butter beans", "Peanut butter"]
+        );
+
+        let resolution = resolver.resolve("tortil").unwrap();
+        assert_eq!(resolution.confidence(), Some(Confidence::Medium));
+        assert_eq!(names(&resolution), vec!["Tortilla wrap"]);
+    }
+
+    #[test]
+    fn resolve_excluding() {
+        let searcher = searcher();
+        let resolver =
+            IngredientResolver::new(&searcher).excluding(&[Ingredient::new("Butter", "butter")]);
+
+        assert!(matches!(
+            resolver.resolve("butter").unwrap(),
+            Resolution::Exact(_)
+        ));
+        assert_eq!(
+            names(&resolver.resolve("butt").unwrap()),
+            vec!["Butter beans", "Peanut butter"]
+        );
+        assert!(matches!(
+            resolver.resolve("nothing like it").unwrap(),
+            Resolution::NotFound
+        ));
+    }
+
+    #[test]
+    fn resolve_input_with_punctuation() {
+        let searcher = searcher();
+        let resolver = IngredientResolver::new(&searcher);
+
+        for input in &["(eggs)", "\"egg", "egg:", "egg -"] {
+            assert_eq!(
+                names(&resolver.resolve(input).unwrap()),
+                vec!["Egg"],
+                "{}",
+                input
+            );
+        }
+        for input in &[
+            "eggs (large",
+            "salt: 1 tsp",
+            "milk -",
+            "a:b",
+            "[butter",
+            ":",
+            "\"",
+        ] {
+            assert!(resolver.resolve(input).is_ok(), "{}", input);
+        }
+    }
+}
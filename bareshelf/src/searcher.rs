@@ -1,22 +1,23 @@
-use std::{
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
-};
+use std::collections::{HashMap, HashSet};
 
 use log::error;
 use tantivy::{
-    collector::{Collector, FacetCollector, TopDocs},
+    collector::{Collector, Count, FacetCollector, FacetCounts, TopDocs},
     fastfield::FacetReader,
-    query::{AllQuery, BooleanQuery, FuzzyTermQuery, Occur, Query, QueryParser, TermQuery},
+    query::{AllQuery, BooleanQuery, FuzzyTermQuery, Occur, Query, TermQuery},
     schema::{Facet, Field, FieldType, IndexRecordOption, Schema, Term},
     tokenizer::Token,
-    DocAddress, DocId, IndexReader, LeasedItem, Score, SegmentReader,
+    DocAddress, DocId, DocSet, IndexReader, LeasedItem, Score, SegmentReader, TERMINATED,
 };
 
 use crate::{
+    allergens::Allergen,
+    co_occurrence::CoOccurrenceCollector,
     datatypes::{Ingredient, IngredientSlug, Recipe},
-    error::Result,
+    error::{Error, Result},
+    key_ingredients::{KeyIngredientFilter, KeyIngredientMode},
     next_ingredient::NextIngredientCollector,
+    planner::MealPlan,
 };
 
 #[derive(Clone)]
@@ -56,33 +57,91 @@ impl Searcher {
     }
 
     /// Popular ingredients that the user does not already have
+    ///
+    /// What counts as popular depends on the query's `Popularity`. Each ingredient comes with the
+    /// number of recipes it was counted in.
     pub fn popular_ingredients(&self, query: RecipeQuery) -> Result<Vec<(Ingredient, u64)>> {
-        // query for all ingredients
         let searcher = self.recipes_reader.searcher();
-        let mut facet_collector =
-            FacetCollector::for_field(self.recipes_schema.get_field("ingredient_slug").unwrap());
-        facet_collector.add_facet("/ingredient");
-        let facet_counts = searcher.search(&AllQuery, &facet_collector)?;
+        let ingredient_slug_field = self.recipes_schema.get_field("ingredient_slug").unwrap();
+
+        // number of recipes and their weight for each ingredient
+        let counts: Vec<(IngredientSlug, u64, f32)> = match query.popularity {
+            Popularity::Shelf | Popularity::CloseToShelf if !query.shelf_ingredients.is_empty() => {
+                let slug_field = self.recipes_schema.get_field("slug").unwrap();
+                let collector = key_ingredient_filter(
+                    &query,
+                    ingredient_slug_field,
+                    CoOccurrenceCollector::new(
+                        ingredient_slug_field,
+                        query.shelf_ingredients.iter().map(Into::into).collect(),
+                        query.popularity == Popularity::CloseToShelf,
+                    ),
+                );
+                searcher
+                    .search(
+                        &self.recipes_query(&query, ingredient_slug_field, slug_field),
+                        &collector,
+                    )?
+                    .into_iter()
+                    .map(|(facet, (count, weight))| (IngredientSlug::from(facet), count, weight))
+                    .collect()
+            }
+            _ => {
+                // query for all ingredients
+                let mut facet_collector = FacetCollector::for_field(ingredient_slug_field);
+                facet_collector.add_facet("/ingredient");
+                searcher
+                    .search(&AllQuery, &facet_collector)?
+                    .get("/ingredient")
+                    .map(|(facet, count)| (IngredientSlug::from(facet), count, count as f32))
+                    .collect()
+            }
+        };
+
+        // ingredients tagged with one of the excluded allergens
+        let allergen_tags = query
+            .excluded_allergens
+            .iter()
+            .flat_map(|allergen| allergen.tags().iter().map(|tag| tag.to_string()))
+            .collect::<Vec<_>>();
+        let allergen_ingredients = if allergen_tags.is_empty() {
+            vec![]
+        } else {
+            self.ingredients(IngredientQuery::by_tags(&allergen_tags))?
+        };
 
         // HashSet of all ingredients in the RecipeQuery
         let query_ingredients = query
             .shelf_ingredients
             .into_iter()
-            .chain(query.key_ingredients.into_iter())
-            .chain(query.banned_ingredients.into_iter())
+            .chain(query.key_ingredients)
+            .chain(query.banned_ingredients)
+            .chain(
+                allergen_ingredients
+                    .into_iter()
+                    .map(|ingredient| IngredientSlug::from(ingredient.slug)),
+            )
             .collect::<HashSet<IngredientSlug>>();
 
-        // get facet counts for all ingredients on in the RecipeQuery
-        let mut result: Vec<(IngredientSlug, u64)> = facet_counts
-            .get("/ingredient")
-            .map(|(facet, count)| (IngredientSlug::from(facet), count))
-            .filter(|(facet, _)| !query_ingredients.contains(facet))
+        // get facet counts for all ingredients not in the RecipeQuery
+        let mut result: Vec<(IngredientSlug, u64, f32)> = counts
+            .into_iter()
+            .filter(|(facet, _, _)| !query_ingredients.contains(facet))
             .collect();
 
-        result.sort_unstable_by_key(|(_, count)| std::cmp::Reverse(*count));
+        result.sort_unstable_by(|(_, a_count, a_weight), (_, b_count, b_weight)| {
+            b_weight
+                .partial_cmp(a_weight)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b_count.cmp(a_count))
+        });
 
         // only grab the top N
-        let results = result.into_iter().take(query.limit).collect::<Vec<_>>();
+        let results = result
+            .into_iter()
+            .take(query.limit)
+            .map(|(slug, count, _)| (slug, count))
+            .collect::<Vec<_>>();
 
         // then query for the full Ingredient items
         let ingredients = self.ingredients(
@@ -112,18 +171,102 @@ impl Searcher {
             .collect())
     }
 
+    /// Number of recipes using an ingredient
+    pub fn ingredient_recipe_count(&self, slug: &str) -> Result<usize> {
+        let ingredient_slug_field = self.recipes_schema.get_field("ingredient_slug").unwrap();
+        let (_, query) = slug_to_query(ingredient_slug_field, Occur::Must)(&slug.into());
+        Ok(self.recipes_reader.searcher().search(&query, &Count)?)
+    }
+
+    /// Ingredients that go well with an ingredient, best first
+    ///
+    /// Pairings are ranked by lift: how much more often the two ingredients appear together than
+    /// they would by chance. This keeps ingredients used everywhere, like salt, from dominating.
+    /// Lift favours rare ingredients, so pairings found in fewer than `MIN_PAIRING_RECIPES`
+    /// recipes are left out unless the ingredient itself is that rare.
+    pub fn ingredient_pairings(&self, slug: &str, limit: usize) -> Result<Vec<IngredientPairing>> {
+        let searcher = self.recipes_reader.searcher();
+        let ingredient_slug_field = self.recipes_schema.get_field("ingredient_slug").unwrap();
+        let slug = IngredientSlug::from(slug);
+        let num_recipes = searcher.num_docs() as f32;
+
+        let mut facet_collector = FacetCollector::for_field(ingredient_slug_field);
+        facet_collector.add_facet("/ingredient");
+        let recipe_counts: HashMap<IngredientSlug, u64> = searcher
+            .search(&AllQuery, &facet_collector)?
+            .get("/ingredient")
+            .map(|(facet, count)| (IngredientSlug::from(facet), count))
+            .collect();
+        let slug_count = match recipe_counts.get(&slug) {
+            Some(count) => *count,
+            None => return Ok(vec![]),
+        };
+        let min_recipes = MIN_PAIRING_RECIPES.min(slug_count);
+
+        let (_, query) = slug_to_query(ingredient_slug_field, Occur::Must)(&slug);
+        let mut facet_collector = FacetCollector::for_field(ingredient_slug_field);
+        facet_collector.add_facet("/ingredient");
+        let mut pairings: Vec<(IngredientSlug, u64, f32)> = searcher
+            .search(&query, &facet_collector)?
+            .get("/ingredient")
+            .map(|(facet, count)| (IngredientSlug::from(facet), count))
+            .filter(|(other, count)| *other != slug && *count >= min_recipes)
+            .map(|(other, count)| {
+                let lift =
+                    count as f32 * num_recipes / (slug_count as f32 * recipe_counts[&other] as f32);
+                (other, count, lift)
+            })
+            .collect();
+        pairings.sort_unstable_by(|(a_slug, a_count, a_lift), (b_slug, b_count, b_lift)| {
+            b_lift
+                .partial_cmp(a_lift)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b_count.cmp(a_count))
+                .then_with(|| a_slug.cmp(b_slug))
+        });
+        pairings.truncate(limit);
+
+        let slugs: Vec<IngredientSlug> = pairings.iter().map(|(slug, _, _)| slug.clone()).collect();
+        let mut ingredients: HashMap<IngredientSlug, Ingredient> = self
+            .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(slugs.len().max(1)))?
+            .into_iter()
+            .map(|ingredient| (IngredientSlug::from(&ingredient.slug), ingredient))
+            .collect();
+
+        Ok(pairings
+            .into_iter()
+            .filter_map(|(slug, recipes, lift)| {
+                let ingredient = ingredients.remove(&slug);
+                if ingredient.is_none() {
+                    error!("could not find {:?}", slug);
+                }
+                Some(IngredientPairing {
+                    ingredient: ingredient?,
+                    recipes,
+                    lift,
+                })
+            })
+            .collect())
+    }
+
     pub fn recipes(&self, query: RecipeQuery) -> Result<RecipeSearchResults> {
         let ingredient_slug_field = self.recipes_schema.get_field("ingredient_slug").unwrap();
+        let slug_field = self.recipes_schema.get_field("slug").unwrap();
 
         let shelf_igredients_set: HashSet<IngredientSlug> =
             query.shelf_ingredients.iter().cloned().collect();
         let searcher = self.recipes_reader.searcher();
 
-        let (recipes, next_ingredients) = searcher.search(
-            &self.recipes_query(&query, ingredient_slug_field),
-            &(
-                self.recipes_doc_collector(&query, ingredient_slug_field),
-                self.recipes_ingredients_collector(&query, ingredient_slug_field),
+        let (recipes, next_ingredients, facet_counts) = searcher.search(
+            &self.recipes_query(&query, ingredient_slug_field, slug_field),
+            &key_ingredient_filter(
+                &query,
+                ingredient_slug_field,
+                (
+                    self.recipes_doc_collector(&query, ingredient_slug_field, slug_field),
+                    self.recipes_ingredients_collector(&query, ingredient_slug_field),
+                    self.recipes_facet_collector(),
+                ),
             ),
         )?;
 
@@ -156,15 +299,56 @@ impl Searcher {
             .map(|(facet, count)| (IngredientSlug::from(facet), *count))
             .collect();
 
-        Ok(RecipeSearchResults::new(query, recipes, next_ingredients))
+        Ok(RecipeSearchResults::new(
+            query,
+            recipes,
+            next_ingredients,
+            RecipeFacetCounts::new(facet_counts),
+        ))
     }
 
-    fn recipes_query(&self, query: &RecipeQuery, ingredient_slug_field: Field) -> BooleanQuery {
-        let key_ingredient_query: Vec<(Occur, Box<dyn Query>)> = if query.key_ingredients.is_empty()
-        {
+    /// Plan a recipe for each of `days` days from the recipes matching a query
+    ///
+    /// The query's limit sets how many recipes are considered.
+    pub fn meal_plan(&self, query: RecipeQuery, days: usize) -> Result<MealPlan> {
+        Ok(MealPlan::new(self.recipes(query)?.recipes, days))
+    }
+
+    fn recipes_query(
+        &self,
+        query: &RecipeQuery,
+        ingredient_slug_field: Field,
+        slug_field: Field,
+    ) -> BooleanQuery {
+        let allergen_field = self.recipes_schema.get_field("allergen").unwrap();
+        let host_field = self.recipes_schema.get_field("host").unwrap();
+        let chef_field = self.recipes_schema.get_field("chef").unwrap();
+        let recipes_query: Vec<(Occur, Box<dyn Query>)> = if query.recipes.is_empty() {
             vec![]
         } else {
             vec![(
+                Occur::Must,
+                Box::new(BooleanQuery::from(
+                    query
+                        .recipes
+                        .iter()
+                        .map(recipe_slug_to_query(slug_field, Occur::Should))
+                        .collect::<Vec<_>>(),
+                )),
+            )]
+        };
+        let key_ingredient_query: Vec<(Occur, Box<dyn Query>)> = match query
+            .key_ingredient_mode
+            .required(query.key_ingredients.len())
+        {
+            0 => vec![],
+            required if required == query.key_ingredients.len() => query
+                .key_ingredients
+                .iter()
+                .map(slug_to_query(ingredient_slug_field, Occur::Must))
+                .collect(),
+            // any more than one are required by `key_ingredient_filter`
+            _ => vec![(
                 Occur::Must,
                 Box::new(BooleanQuery::from(
                     query
@@ -173,20 +357,42 @@ impl Searcher {
                         .map(slug_to_query(ingredient_slug_field, Occur::Should))
                         .collect::<Vec<_>>(),
                 )),
-            )]
+            )],
         };
         BooleanQuery::from(
             query
                 .shelf_ingredients
                 .iter()
                 .map(slug_to_query(ingredient_slug_field, Occur::Should))
-                .chain(key_ingredient_query.into_iter())
+                .chain(key_ingredient_query)
+                .chain(recipes_query)
+                .chain(any_facet_query(host_field, "host", &query.hosts))
+                .chain(any_facet_query(chef_field, "chef", &query.chefs))
+                .chain(
+                    query
+                        .required_ingredients
+                        .iter()
+                        .map(slug_to_query(ingredient_slug_field, Occur::Must)),
+                )
                 .chain(
                     query
                         .banned_ingredients
                         .iter()
                         .map(slug_to_query(ingredient_slug_field, Occur::MustNot)),
                 )
+                .chain(
+                    query
+                        .excluded_recipes
+                        .iter()
+                        .map(recipe_slug_to_query(slug_field, Occur::MustNot)),
+                )
+                .chain(query.excluded_allergens.iter().map(|allergen| {
+                    let query: Box<dyn Query> = Box::new(TermQuery::new(
+                        Term::from_facet(allergen_field, &allergen.facet()),
+                        IndexRecordOption::Basic,
+                    ));
+                    (Occur::MustNot, query)
+                }))
                 .collect::<Vec<_>>(),
         )
     }
@@ -195,27 +401,54 @@ impl Searcher {
         &self,
         query: &RecipeQuery,
         ingredient_slug_field: Field,
+        slug_field: Field,
     ) -> impl Collector<Fruit = Vec<(f32, DocAddress)>> {
         let ingredients_facets: Vec<Facet> =
             query.shelf_ingredients.iter().map(Into::into).collect();
+        let (priority_facets, priority_weights): (Vec<Facet>, Vec<f32>) = query
+            .prioritised_ingredients
+            .iter()
+            .map(|(slug, weight)| (Facet::from(slug), *weight))
+            .unzip();
+        let demoted_recipes = query.demoted_recipes.clone();
 
         TopDocs::with_limit(query.limit).tweak_score(move |segment_reader: &SegmentReader| {
             let mut ingredient_reader = segment_reader.facet_reader(ingredient_slug_field).unwrap();
             let query_ords = get_query_ords(&ingredients_facets, &ingredient_reader);
+            let priority_ords: HashMap<u64, f32> =
+                get_query_ords_with(&priority_facets, &priority_weights, &ingredient_reader);
+            let demoted_docs = get_slug_docs(&demoted_recipes, segment_reader, slug_field);
             let mut facet_ords_buffer = Vec::with_capacity(20);
 
             move |doc: DocId, original_score: Score| {
+                let demotion = demoted_docs.get(&doc).copied().unwrap_or(0.0);
                 calculate_score(
                     doc,
                     original_score,
                     &mut ingredient_reader,
                     &mut facet_ords_buffer,
                     &query_ords,
-                )
+                    &priority_ords,
+                ) / 4_f32.powf(demotion)
             }
         })
     }
 
+    /// Counts of the matching recipes by host, chef and ingredient
+    fn recipes_facet_collector(&self) -> (FacetCollector, FacetCollector, FacetCollector) {
+        let collector = |field: &str, facet: &str| {
+            let mut collector =
+                FacetCollector::for_field(self.recipes_schema.get_field(field).unwrap());
+            collector.add_facet(facet);
+            collector
+        };
+        (
+            collector("host", "/host"),
+            collector("chef", "/chef"),
+            collector("ingredient_slug", "/ingredient"),
+        )
+    }
+
     fn recipes_ingredients_collector(
         &self,
         query: &RecipeQuery,
@@ -229,9 +462,14 @@ impl Searcher {
 
     pub fn ingredients(&self, query: IngredientQuery) -> Result<Vec<Ingredient>> {
         let searcher = self.ingredients_reader.searcher();
+        let limit = query.limit.unwrap_or(match query.by {
+            // every tagged ingredient, they are usually wanted as a set
+            IngredientQueryBy::Tags(_) => (searcher.num_docs() as usize).max(1),
+            _ => 20,
+        });
         let top_docs = searcher.search(
-            &self.ingredients_query(&query),
-            &TopDocs::with_limit(query.limit.unwrap_or(20)),
+            &self.ingredients_query(&query)?,
+            &TopDocs::with_limit(limit),
         )?;
         let top_docs = self
             .load_ingredients(&searcher, top_docs)
@@ -242,38 +480,41 @@ impl Searcher {
         Ok(self.post_process_ingredients(&query, top_docs))
     }
 
-    fn ingredients_query(&self, query: &IngredientQuery) -> Box<dyn Query> {
+    fn ingredients_query(&self, query: &IngredientQuery) -> Result<Box<dyn Query>> {
         let name_field = self.ingredients_schema.get_field("name").unwrap();
         let slug_field = self.ingredients_schema.get_field("slug").unwrap();
+        let aliases_field = self.ingredients_schema.get_field("aliases").unwrap();
 
-        match &query.by {
+        Ok(match &query.by {
             IngredientQueryBy::Prefix(prefix) => {
-                let tokens = get_field_tokens(
-                    &self.ingredients_index,
-                    &self.ingredients_schema,
-                    name_field,
-                    prefix,
-                )
-                .unwrap();
+                let tokens = self.name_tokens(name_field, prefix)?;
+                // every token must prefix match either the name or the aliases
                 Box::new(BooleanQuery::from(
-                    tokens
+                    [name_field, aliases_field]
                         .iter()
-                        .map(|token| {
-                            let query: Box<dyn Query> = Box::new(FuzzyTermQuery::new_prefix(
-                                Term::from_field_text(name_field, &token.text),
-                                0,
-                                true,
-                            ));
-                            (Occur::Must, query)
+                        .map(|field| {
+                            let query: Box<dyn Query> =
+                                Box::new(BooleanQuery::from(prefix_queries(*field, &tokens)));
+                            (Occur::Should, query)
+                        })
+                        .collect::<Vec<_>>(),
+                ))
+            }
+            IngredientQueryBy::Name(name) => {
+                // user input, tokenised rather than parsed so punctuation is not query syntax
+                let tokens = self.name_tokens(name_field, name)?;
+                // every token must match either the name or the aliases
+                Box::new(BooleanQuery::from(
+                    [name_field, aliases_field]
+                        .iter()
+                        .map(|field| {
+                            let query: Box<dyn Query> =
+                                Box::new(BooleanQuery::from(term_queries(*field, &tokens)));
+                            (Occur::Should, query)
                         })
                         .collect::<Vec<_>>(),
                 ))
             }
-            IngredientQueryBy::Name(name) => Box::new(
-                QueryParser::for_index(&self.ingredients_index, vec![name_field])
-                    .parse_query(name)
-                    .unwrap(),
-            ),
             IngredientQueryBy::Slugs(slugs) => {
                 let term_queries: Vec<(Occur, Box<dyn Query>)> = slugs
                     .iter()
@@ -287,8 +528,32 @@ impl Searcher {
                     .collect();
                 Box::new(BooleanQuery::from(term_queries))
             }
+            IngredientQueryBy::Tags(tags) => {
+                let tags_field = self.ingredients_schema.get_field("tags").unwrap();
+                let term_queries: Vec<(Occur, Box<dyn Query>)> = tags
+                    .iter()
+                    .map(|tag| {
+                        let term_query: Box<dyn Query> = Box::new(TermQuery::new(
+                            Term::from_field_text(tags_field, tag),
+                            IndexRecordOption::Basic,
+                        ));
+                        (Occur::Should, term_query)
+                    })
+                    .collect();
+                Box::new(BooleanQuery::from(term_queries))
+            }
             IngredientQueryBy::All => Box::new(AllQuery),
-        }
+        })
+    }
+
+    fn name_tokens(&self, name_field: Field, input: &str) -> Result<Vec<Token>> {
+        get_field_tokens(
+            &self.ingredients_index,
+            &self.ingredients_schema,
+            name_field,
+            input,
+        )
+        .ok_or_else(|| Error::Other("ingredient names are not tokenized".to_string()))
     }
 
     fn post_process_ingredients(
@@ -296,36 +561,40 @@ impl Searcher {
         query: &IngredientQuery,
         mut top_docs: Vec<Ingredient>,
     ) -> Vec<Ingredient> {
+        let name_field = self.ingredients_schema.get_field("name").unwrap();
+        let tokenize = |text: &str| {
+            get_field_tokens(
+                &self.ingredients_index,
+                &self.ingredients_schema,
+                name_field,
+                text,
+            )
+            .unwrap_or_default()
+        };
         match &query.by {
             IngredientQueryBy::Prefix(prefix) => {
-                let name_field = self.ingredients_schema.get_field("name").unwrap();
-                let tokens = get_field_tokens(
-                    &self.ingredients_index,
-                    &self.ingredients_schema,
-                    name_field,
-                    prefix,
-                )
-                .unwrap();
-                // Use the same sorting as used by the materialize autocomplete
-                top_docs.sort_by(|left, right| {
-                    let order = left
-                        .name
-                        .to_lowercase()
-                        .find(&tokens[0].text)
-                        .cmp(&right.name.to_lowercase().find(&tokens[0].text));
-                    if order == Ordering::Equal {
-                        left.name.len().cmp(&right.name.len())
-                    } else {
-                        order
-                    }
-                });
+                if let Some(first) = tokenize(prefix).first() {
+                    // Use the same sorting as used by the materialize autocomplete, taking the
+                    // best position across the name and aliases. Positions come from the stemmed
+                    // tokens so that "berries" still sorts by where "berri" starts.
+                    top_docs.sort_by_cached_key(|ingredient| {
+                        let position = ingredient
+                            .names()
+                            .filter_map(|name| {
+                                tokenize(name)
+                                    .iter()
+                                    .find(|token| token.text.starts_with(&first.text))
+                                    .map(|token| token.offset_from)
+                            })
+                            .min();
+                        (position.is_none(), position, ingredient.name.len())
+                    });
+                }
             }
             IngredientQueryBy::Name(name) => {
-                top_docs = top_docs
-                    .iter()
-                    .cloned()
-                    .filter(|i| i.name.to_lowercase() == name.to_lowercase())
-                    .collect();
+                // compare stemmed tokens so that singular and plural forms are equal
+                let terms = token_texts(tokenize(name));
+                top_docs.retain(|i| i.names().any(|n| token_texts(tokenize(n)) == terms));
             }
             _ => {}
         }
@@ -342,6 +611,8 @@ impl Searcher {
     ) -> Vec<(Score, Ingredient)> {
         let name_field = self.ingredients_schema.get_field("name").unwrap();
         let slug_field = self.ingredients_schema.get_field("slug").unwrap();
+        let aliases_field = self.ingredients_schema.get_field("aliases").unwrap();
+        let tags_field = self.ingredients_schema.get_field("tags").unwrap();
 
         top_docs
             .iter()
@@ -361,8 +632,21 @@ impl Searcher {
                     .as_text()
                     .unwrap()
                     .to_string();
-
-                (*score, Ingredient::new(&name, &slug))
+                let aliases = document
+                    .get_all(aliases_field)
+                    .filter_map(|alias| alias.as_text())
+                    .collect::<Vec<_>>();
+                let tags = document
+                    .get_all(tags_field)
+                    .filter_map(|tag| tag.as_text())
+                    .collect::<Vec<_>>();
+
+                (
+                    *score,
+                    Ingredient::new(&name, &slug)
+                        .with_aliases(&aliases)
+                        .with_tags(&tags),
+                )
             })
             .collect()
     }
@@ -374,6 +658,30 @@ pub struct RecipeQuery {
     shelf_ingredients: Vec<IngredientSlug>,
     key_ingredients: Vec<IngredientSlug>,
     banned_ingredients: Vec<IngredientSlug>,
+    prioritised_ingredients: Vec<(IngredientSlug, f32)>,
+    recipes: Vec<String>,
+    excluded_recipes: Vec<String>,
+    demoted_recipes: Vec<(String, f32)>,
+    excluded_allergens: Vec<Allergen>,
+    hosts: Vec<String>,
+    chefs: Vec<String>,
+    required_ingredients: Vec<IngredientSlug>,
+    popularity: Popularity,
+    key_ingredient_mode: KeyIngredientMode,
+}
+
+/// What makes an ingredient popular, see `Searcher::popular_ingredients`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Popularity {
+    /// Used by the most recipes in the index
+    Overall,
+    /// Used by the most recipes sharing an ingredient with the shelf
+    ///
+    /// Recipes the query rules out, eg. with banned ingredients, are not counted. Falls back to
+    /// `Overall` when the shelf is empty.
+    Shelf,
+    /// Like `Shelf`, but recipes count for more the more of their ingredients are on the shelf
+    CloseToShelf,
 }
 
 impl Default for RecipeQuery {
@@ -383,6 +691,16 @@ impl Default for RecipeQuery {
             shelf_ingredients: vec![],
             key_ingredients: vec![],
             banned_ingredients: vec![],
+            prioritised_ingredients: vec![],
+            recipes: vec![],
+            excluded_recipes: vec![],
+            demoted_recipes: vec![],
+            excluded_allergens: vec![],
+            hosts: vec![],
+            chefs: vec![],
+            required_ingredients: vec![],
+            popularity: Popularity::Overall,
+            key_ingredient_mode: KeyIngredientMode::Any,
         }
     }
 }
@@ -408,6 +726,12 @@ impl RecipeQuery {
         self
     }
 
+    /// How many of the key ingredients a recipe needs, one by default
+    pub fn key_ingredient_mode(mut self, mode: KeyIngredientMode) -> Self {
+        self.key_ingredient_mode = mode;
+        self
+    }
+
     /// Set which ingredients are banned
     ///
     /// A Recipe must not contain these to be returned.
@@ -415,6 +739,125 @@ impl RecipeQuery {
         self.banned_ingredients = ingredients.iter().map(IngredientSlug::from).collect();
         self
     }
+
+    /// Set ingredients that recipes should use up, with how urgently
+    ///
+    /// Each weight is usually between 0 and 1. Each missing ingredient divides a recipe's score
+    /// by 4, and the weights of the prioritised ingredients a recipe uses are added up and
+    /// multiply it by 4 to that power. So using an ingredient with weight 1 makes up for one
+    /// missing ingredient.
+    pub fn prioritise_ingredients(mut self, ingredients: &[(String, f32)]) -> Self {
+        self.prioritised_ingredients = ingredients
+            .iter()
+            .map(|(slug, weight)| (IngredientSlug::from(slug), *weight))
+            .collect();
+        self
+    }
+
+    /// Only return recipes with these slugs
+    pub fn recipes(mut self, slugs: &[String]) -> Self {
+        self.recipes = slugs.to_vec();
+        self
+    }
+
+    /// Never return recipes with these slugs
+    pub fn excluded_recipes(mut self, slugs: &[String]) -> Self {
+        self.excluded_recipes = slugs.to_vec();
+        self
+    }
+
+    /// Set recipes that should rank lower, with how strongly, by slug
+    ///
+    /// A recipe's score is divided by 4 to the power of its weight, so a weight of 1 counts
+    /// the same as one missing ingredient.
+    pub fn demote_recipes(mut self, slugs: &[(String, f32)]) -> Self {
+        self.demoted_recipes = slugs.to_vec();
+        self
+    }
+
+    /// Never return recipes containing any of these allergens
+    pub fn excluded_allergens(mut self, allergens: &[Allergen]) -> Self {
+        self.excluded_allergens = allergens.to_vec();
+        self
+    }
+
+    /// Only return recipes from one of these sites, by host name
+    pub fn hosts(mut self, hosts: &[String]) -> Self {
+        self.hosts = hosts.to_vec();
+        self
+    }
+
+    /// Only return recipes by one of these chefs
+    pub fn chefs(mut self, chefs: &[String]) -> Self {
+        self.chefs = chefs.to_vec();
+        self
+    }
+
+    /// How `Searcher::popular_ingredients` ranks ingredients
+    pub fn popularity(mut self, popularity: Popularity) -> Self {
+        self.popularity = popularity;
+        self
+    }
+
+    /// Only return recipes using all of these ingredients
+    ///
+    /// Every one of these must appear, whatever the `KeyIngredientMode` says about key ingredients.
+    pub fn required_ingredients(mut self, ingredients: &[String]) -> Self {
+        self.required_ingredients = ingredients.iter().map(IngredientSlug::from).collect();
+        self
+    }
+}
+
+/// Wrap a collector to drop recipes with too few key ingredients
+///
+/// Only needed when some but not all key ingredients are required, `Searcher::recipes_query`
+/// handles the rest.
+fn key_ingredient_filter<C: Collector>(
+    query: &RecipeQuery,
+    ingredient_slug_field: Field,
+    collector: C,
+) -> KeyIngredientFilter<C> {
+    let count = query.key_ingredients.len();
+    let required = match query.key_ingredient_mode.required(count) {
+        required if required < count => required,
+        _ => 0,
+    };
+    KeyIngredientFilter::new(
+        ingredient_slug_field,
+        query.key_ingredients.iter().map(Into::into).collect(),
+        required,
+        collector,
+    )
+}
+
+/// A clause matching recipes with any of the values of a facet, or nothing if there are none
+fn any_facet_query(field: Field, name: &str, values: &[String]) -> Vec<(Occur, Box<dyn Query>)> {
+    if values.is_empty() {
+        return vec![];
+    }
+    let queries: Vec<(Occur, Box<dyn Query>)> = values
+        .iter()
+        .map(|value| {
+            let query: Box<dyn Query> = Box::new(TermQuery::new(
+                Term::from_facet(field, &Facet::from_path([name, value])),
+                IndexRecordOption::Basic,
+            ));
+            (Occur::Should, query)
+        })
+        .collect();
+    vec![(Occur::Must, Box::new(BooleanQuery::from(queries)))]
+}
+
+fn recipe_slug_to_query(field: Field, occur: Occur) -> impl Fn(&String) -> (Occur, Box<dyn Query>) {
+    move |slug| {
+        (
+            occur,
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, slug),
+                IndexRecordOption::Basic,
+            )),
+        )
+    }
 }
 
 fn slug_to_query(
@@ -442,6 +885,7 @@ enum IngredientQueryBy<'a> {
     Prefix(String),
     Name(String),
     Slugs(&'a [IngredientSlug]),
+    Tags(&'a [String]),
     All,
 }
 
@@ -458,6 +902,13 @@ impl<'a> IngredientQuery<'a> {
         Self::by(IngredientQueryBy::Slugs(slugs))
     }
 
+    /// Ingredients with any of the tags
+    ///
+    /// Returns every matching ingredient unless a limit is set.
+    pub fn by_tags(tags: &'a [String]) -> Self {
+        Self::by(IngredientQueryBy::Tags(tags))
+    }
+
     pub fn all() -> Self {
         Self::by(IngredientQueryBy::All)
     }
@@ -481,6 +932,37 @@ impl<'a> IngredientQuery<'a> {
     }
 }
 
+fn token_texts(tokens: Vec<Token>) -> Vec<String> {
+    tokens.into_iter().map(|token| token.text).collect()
+}
+
+fn prefix_queries(field: Field, tokens: &[Token]) -> Vec<(Occur, Box<dyn Query>)> {
+    tokens
+        .iter()
+        .map(|token| {
+            let query: Box<dyn Query> = Box::new(FuzzyTermQuery::new_prefix(
+                Term::from_field_text(field, &token.text),
+                0,
+                true,
+            ));
+            (Occur::Must, query)
+        })
+        .collect()
+}
+
+fn term_queries(field: Field, tokens: &[Token]) -> Vec<(Occur, Box<dyn Query>)> {
+    tokens
+        .iter()
+        .map(|token| {
+            let query: Box<dyn Query> = Box::new(TermQuery::new(
+                Term::from_field_text(field, &token.text),
+                IndexRecordOption::Basic,
+            ));
+            (Occur::Must, query)
+        })
+        .collect()
+}
+
 fn get_field_tokens(
     index: &tantivy::Index,
     schema: &Schema,
@@ -511,6 +993,7 @@ pub struct RecipeSearchResults {
     query: RecipeQuery,
     recipes: Vec<RecipeSearchResult>,
     next_ingredients: HashMap<IngredientSlug, usize>,
+    facet_counts: RecipeFacetCounts,
 }
 
 impl RecipeSearchResults {
@@ -518,11 +1001,13 @@ impl RecipeSearchResults {
         query: RecipeQuery,
         recipes: Vec<RecipeSearchResult>,
         next_ingredients: HashMap<IngredientSlug, usize>,
+        facet_counts: RecipeFacetCounts,
     ) -> Self {
         Self {
             query,
             recipes,
             next_ingredients,
+            facet_counts,
         }
     }
 
@@ -551,6 +1036,45 @@ impl RecipeSearchResults {
     pub fn next_ingredients(&self) -> &HashMap<IngredientSlug, usize> {
         &self.next_ingredients
     }
+
+    /// How many recipes match the query by host, chef and ingredient
+    ///
+    /// Counts cover every matching recipe, not only those returned.
+    pub fn facet_counts(&self) -> &RecipeFacetCounts {
+        &self.facet_counts
+    }
+}
+
+/// Counts of recipes by host, chef and ingredient, most recipes first
+pub struct RecipeFacetCounts {
+    pub hosts: Vec<(String, u64)>,
+    pub chefs: Vec<(String, u64)>,
+    pub ingredients: Vec<(IngredientSlug, u64)>,
+}
+
+impl RecipeFacetCounts {
+    fn new((hosts, chefs, ingredients): (FacetCounts, FacetCounts, FacetCounts)) -> Self {
+        Self {
+            hosts: sorted_counts(&hosts, "/host", |facet| facet.to_path()[1].to_owned()),
+            chefs: sorted_counts(&chefs, "/chef", |facet| facet.to_path()[1].to_owned()),
+            ingredients: sorted_counts(&ingredients, "/ingredient", |facet| {
+                IngredientSlug::from(facet)
+            }),
+        }
+    }
+}
+
+fn sorted_counts<T: Ord>(
+    counts: &FacetCounts,
+    facet: &str,
+    value: impl Fn(&Facet) -> T,
+) -> Vec<(T, u64)> {
+    let mut counts: Vec<(T, u64)> = counts
+        .get(facet)
+        .map(|(facet, count)| (value(facet), count))
+        .collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts
 }
 
 pub struct RecipeSearchResult {
@@ -559,6 +1083,19 @@ pub struct RecipeSearchResult {
     pub missing_ingredients: Vec<String>,
 }
 
+/// How few recipes two ingredients can share and still be paired
+const MIN_PAIRING_RECIPES: u64 = 2;
+
+/// An ingredient that goes well with another, see `Searcher::ingredient_pairings`
+#[derive(Debug)]
+pub struct IngredientPairing {
+    pub ingredient: Ingredient,
+    /// Number of recipes using both ingredients
+    pub recipes: u64,
+    /// How many times more often the ingredients appear together than they would by chance
+    pub lift: f32,
+}
+
 fn get_query_ords(facets: &[Facet], ingredient_reader: &FacetReader) -> HashSet<u64> {
     let facet_dict = ingredient_reader.facet_dict();
 
@@ -572,12 +1109,61 @@ fn get_query_ords(facets: &[Facet], ingredient_reader: &FacetReader) -> HashSet<
         .collect()
 }
 
+/// Like `get_query_ords` but keeping a value for each facet found
+fn get_query_ords_with<T: Copy>(
+    facets: &[Facet],
+    values: &[T],
+    ingredient_reader: &FacetReader,
+) -> HashMap<u64, T> {
+    let facet_dict = ingredient_reader.facet_dict();
+
+    facets
+        .iter()
+        .zip(values)
+        .filter_map(|(key, value)| {
+            facet_dict
+                .term_ord(key.encoded_str())
+                .expect("IO error here implies the index is borked")
+                .map(|ord| (ord, *value))
+        })
+        .collect()
+}
+
+/// Find the documents in a segment for recipe slugs, keeping a value for each
+fn get_slug_docs<T: Copy>(
+    slugs: &[(String, T)],
+    segment_reader: &SegmentReader,
+    slug_field: Field,
+) -> HashMap<DocId, T> {
+    let mut docs = HashMap::new();
+    if slugs.is_empty() {
+        return docs;
+    }
+    let inverted_index = segment_reader
+        .inverted_index(slug_field)
+        .expect("IO error here implies the index is borked");
+    for (slug, value) in slugs {
+        let term = Term::from_field_text(slug_field, slug);
+        let postings = inverted_index
+            .read_postings(&term, IndexRecordOption::Basic)
+            .expect("IO error here implies the index is borked");
+        if let Some(mut postings) = postings {
+            while postings.doc() != TERMINATED {
+                docs.insert(postings.doc(), *value);
+                postings.advance();
+            }
+        }
+    }
+    docs
+}
+
 fn calculate_score(
     doc: DocId,
     original_score: Score,
     ingredient_reader: &mut FacetReader,
     facet_ords_buffer: &mut Vec<u64>,
     query_ords: &HashSet<u64>,
+    priority_ords: &HashMap<u64, f32>,
 ) -> Score {
     ingredient_reader.facet_ords(doc, facet_ords_buffer);
     let missing_ingredients = facet_ords_buffer
@@ -585,13 +1171,21 @@ fn calculate_score(
         .filter(|o| !query_ords.contains(o))
         .count();
     let tweak = 1.0 / 4_f32.powi(missing_ingredients as i32);
+    let priority: f32 = facet_ords_buffer
+        .iter()
+        .filter_map(|o| priority_ords.get(o))
+        .sum();
 
-    original_score * tweak
+    original_score * tweak * 4_f32.powf(priority)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Ingredient, IngredientQuery, RecipeQuery, Searcher};
+    use std::collections::HashMap;
+
+    use super::{
+        Allergen, Ingredient, IngredientQuery, KeyIngredientMode, Popularity, RecipeQuery, Searcher,
+    };
     use crate::tests::{setup_indexes, setup_ingredients_index, setup_recipes_index};
 
     #[derive(Default, Debug)]
@@ -650,6 +1244,108 @@ mod tests {
         assert_eq!(2, result[1].1);
     }
 
+    #[test]
+    fn popular_ingredients_near_shelf() {
+        let (recipes_index, ingredients_index) = setup_indexes();
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let popular = |popularity| {
+            searcher
+                .popular_ingredients(
+                    RecipeQuery::default()
+                        .shelf_ingredients(&["milk".to_string(), "salt".to_string()])
+                        .popularity(popularity)
+                        .limit(2),
+                )
+                .unwrap()
+                .into_iter()
+                .map(|(ingredient, count)| (ingredient.slug, count))
+                .collect::<Vec<_>>()
+        };
+
+        // oil is in two recipes, neither sharing an ingredient with the shelf
+        assert_eq!(
+            popular(Popularity::Overall),
+            vec![("egg".to_string(), 3), ("oil".to_string(), 2)]
+        );
+        // half of scrambled egg is on the shelf, but only a sixth of egg rolls
+        assert_eq!(
+            popular(Popularity::CloseToShelf),
+            vec![("egg".to_string(), 2), ("butter".to_string(), 1)]
+        );
+
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&["butter".to_string()])
+            .popularity(Popularity::Shelf);
+        let mut popular: Vec<String> = searcher
+            .popular_ingredients(query)
+            .unwrap()
+            .into_iter()
+            .map(|(ingredient, _)| ingredient.slug)
+            .collect();
+        popular.sort();
+        assert_eq!(popular, vec!["egg", "milk", "salt"]);
+    }
+
+    #[test]
+    fn popular_ingredients_leave_out_allergens() {
+        let (recipes_index, ingredients_index) = setup_indexes();
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        let query = RecipeQuery::default()
+            .popularity(Popularity::Overall)
+            .excluded_allergens(&[Allergen::Milk]);
+        let popular: Vec<String> = searcher
+            .popular_ingredients(query)
+            .unwrap()
+            .into_iter()
+            .map(|(ingredient, _)| ingredient.slug)
+            .collect();
+
+        assert!(popular.contains(&"egg".to_string()));
+        assert!(!popular.contains(&"butter".to_string()));
+        assert!(!popular.contains(&"milk".to_string()));
+    }
+
+    #[test]
+    fn ingredient_pairings_by_lift() {
+        let (recipes_index, ingredients_index) = setup_indexes();
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let pairings = |slug, limit| {
+            searcher
+                .ingredient_pairings(slug, limit)
+                .unwrap()
+                .into_iter()
+                .map(|pairing| (pairing.ingredient.slug, pairing.recipes, pairing.lift))
+                .collect::<Vec<_>>()
+        };
+
+        // garlic is only in egg rolls, egg is in every recipe so says nothing about it
+        assert_eq!(
+            pairings("garlic", 10),
+            vec![
+                ("mushroom".to_string(), 1, 3.0),
+                ("tortilla-wrap".to_string(), 1, 3.0),
+                ("oil".to_string(), 1, 1.5),
+                ("salt".to_string(), 1, 1.5),
+                ("egg".to_string(), 1, 1.0),
+            ]
+        );
+        assert_eq!(pairings("garlic", 1).len(), 1);
+        // oil shares a single recipe with salt
+        assert_eq!(pairings("oil", 10), vec![("egg".to_string(), 2, 1.0)]);
+        assert!(pairings("unknown", 10).is_empty());
+    }
+
+    #[test]
+    fn count_recipes_using_an_ingredient() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        assert_eq!(searcher.ingredient_recipe_count("egg").unwrap(), 3);
+        assert_eq!(searcher.ingredient_recipe_count("salt").unwrap(), 2);
+        assert_eq!(searcher.ingredient_recipe_count("unknown").unwrap(), 0);
+    }
+
     #[test]
     fn tweak_score_with_facets() {
         let (recipes_index, ingredients_index) = setup_recipes_index();
@@ -677,6 +1373,197 @@ mod tests {
         );
     }
 
+    #[test]
+    fn prioritised_ingredients_are_boosted() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let shelf_ingredients = [
+            "egg".to_string(),
+            "oil".to_string(),
+            "butter".to_string(),
+            "milk".to_string(),
+            "salt".to_string(),
+        ];
+        let scores = |query| {
+            searcher
+                .recipes(query)
+                .unwrap()
+                .all()
+                .iter()
+                .map(|r| (r.recipe.title.to_owned(), r.score))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let before = scores(RecipeQuery::default().shelf_ingredients(&shelf_ingredients));
+        let after = scores(
+            RecipeQuery::default()
+                .shelf_ingredients(&shelf_ingredients)
+                .prioritise_ingredients(&[("oil".to_string(), 1.0), ("sugar".to_string(), 1.0)]),
+        );
+
+        assert!((after["Fried egg"] - before["Fried egg"] * 4.0).abs() < 1e-4);
+        assert!((after["Scrambled egg"] - before["Scrambled egg"]).abs() < 1e-4);
+    }
+
+    #[test]
+    fn recipes_list_missing_ingredients() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        let query = RecipeQuery::default().shelf_ingredients(&["egg".to_string()]);
+        let results = searcher.recipes(query).unwrap();
+        let fried_egg = results
+            .all()
+            .iter()
+            .find(|r| r.recipe.slug == "fried-egg")
+            .unwrap();
+
+        assert_eq!(fried_egg.recipe.ingredients.len(), 2);
+        assert_eq!(fried_egg.missing_ingredients, vec!["oil".to_string()]);
+    }
+
+    #[test]
+    fn only_return_chosen_recipes() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&["egg".to_string()])
+            .recipes(&["fried-egg".to_string(), "egg-rolls".to_string()]);
+        let mut slugs: Vec<_> = searcher
+            .recipes(query)
+            .unwrap()
+            .all()
+            .iter()
+            .map(|r| r.recipe.slug.to_owned())
+            .collect();
+        slugs.sort();
+
+        assert_eq!(slugs, vec!["egg-rolls", "fried-egg"]);
+    }
+
+    #[test]
+    fn excluded_recipes_are_not_returned() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&["egg".to_string(), "oil".to_string()])
+            .excluded_recipes(&["fried-egg".to_string()]);
+        let results = searcher.recipes(query).unwrap();
+
+        assert!(!results.all().is_empty());
+        assert!(results.all().iter().all(|r| r.recipe.slug != "fried-egg"));
+    }
+
+    #[test]
+    fn recipes_with_excluded_allergens_are_not_returned() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let query = RecipeQuery::default().shelf_ingredients(&["egg".to_string()]);
+        let results = searcher.recipes(query).unwrap();
+        let scrambled_egg = results
+            .all()
+            .iter()
+            .find(|r| r.recipe.slug == "scrambled-egg")
+            .unwrap();
+        assert_eq!(scrambled_egg.recipe.allergens, vec![Allergen::Milk]);
+
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&["egg".to_string()])
+            .excluded_allergens(&[Allergen::Milk]);
+        let results = searcher.recipes(query).unwrap();
+
+        assert_eq!(results.all().len(), 2);
+        assert!(results.all().iter().all(|r| r.recipe.allergens.is_empty()));
+    }
+
+    #[test]
+    fn recipes_are_counted_by_facet() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&["egg".to_string()])
+            .limit(1);
+        let results = searcher.recipes(query).unwrap();
+        let counts = results.facet_counts();
+
+        assert_eq!(
+            counts.hosts,
+            vec![
+                ("example.org".to_string(), 2),
+                ("example.com".to_string(), 1)
+            ]
+        );
+        assert_eq!(counts.chefs, vec![("Nigel Slater".to_string(), 1)]);
+        assert_eq!(counts.ingredients[0], ("egg".into(), 3));
+        assert_eq!(counts.ingredients[1], ("oil".into(), 2));
+        assert_eq!(counts.ingredients[2], ("salt".into(), 2));
+    }
+
+    #[test]
+    fn recipes_are_filtered_by_facet() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let titles = |query| {
+            searcher
+                .recipes(query)
+                .unwrap()
+                .all()
+                .iter()
+                .map(|r| r.recipe.title.to_owned())
+                .collect::<Vec<String>>()
+        };
+        let query = || RecipeQuery::default().shelf_ingredients(&["egg".to_string()]);
+
+        assert_eq!(
+            titles(query().hosts(&["example.com".to_string(), "example.net".to_string()])),
+            vec!["Egg rolls"]
+        );
+        assert_eq!(
+            titles(query().chefs(&["Nigel Slater".to_string()])),
+            vec!["Egg rolls"]
+        );
+        assert_eq!(
+            titles(query().required_ingredients(&["salt".to_string(), "milk".to_string()])),
+            vec!["Scrambled egg"]
+        );
+    }
+
+    #[test]
+    fn demoted_recipes_rank_lower() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let shelf_ingredients = ["egg".to_string(), "oil".to_string()];
+        let scores = |query| {
+            searcher
+                .recipes(query)
+                .unwrap()
+                .all()
+                .iter()
+                .map(|r| (r.recipe.title.to_owned(), r.score))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let before = scores(RecipeQuery::default().shelf_ingredients(&shelf_ingredients));
+        let after = scores(
+            RecipeQuery::default()
+                .shelf_ingredients(&shelf_ingredients)
+                .demote_recipes(&[("fried-egg".to_string(), 2.0), ("unknown".to_string(), 1.0)]),
+        );
+
+        assert!((after["Fried egg"] - before["Fried egg"] / 16.0).abs() < 1e-4);
+        assert!((after["Scrambled egg"] - before["Scrambled egg"]).abs() < 1e-4);
+    }
+
     #[test]
     fn one_of_key_ingredients_must_appear() {
         let (recipes_index, ingredients_index) = setup_recipes_index();
@@ -692,6 +1579,51 @@ mod tests {
         assert_eq!(results.all().len(), 2);
     }
 
+    #[test]
+    fn key_ingredient_modes() {
+        let (recipes_index, ingredients_index) = setup_recipes_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let titles = |key_ingredients: &[&str], mode| {
+            let key_ingredients: Vec<String> = key_ingredients
+                .iter()
+                .map(|slug| slug.to_string())
+                .collect();
+            let results = searcher
+                .recipes(
+                    RecipeQuery::default()
+                        .shelf_ingredients(&["egg".to_string()])
+                        .key_ingredients(&key_ingredients)
+                        .key_ingredient_mode(mode),
+                )
+                .unwrap();
+            let mut titles: Vec<String> = results
+                .all()
+                .iter()
+                .map(|r| r.recipe.title.to_owned())
+                .collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(
+            titles(&["salt", "oil"], KeyIngredientMode::Any),
+            vec!["Egg rolls", "Fried egg", "Scrambled egg"]
+        );
+        assert_eq!(
+            titles(&["salt", "oil"], KeyIngredientMode::All),
+            vec!["Egg rolls"]
+        );
+        assert_eq!(
+            titles(&["salt", "oil", "milk"], KeyIngredientMode::AtLeast(2)),
+            vec!["Egg rolls", "Scrambled egg"]
+        );
+        assert_eq!(
+            titles(&["salt", "oil"], KeyIngredientMode::AtLeast(3)),
+            vec!["Egg rolls"]
+        );
+    }
+
     #[test]
     fn ingredients_by_name() {
         let (recipes_index, ingredients_index) = setup_ingredients_index();
@@ -715,6 +1647,88 @@ mod tests {
         assert_eq!(ingredients[0].name, "Brown sugar");
     }
 
+    #[test]
+    fn ingredients_by_name_matches_aliases() {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let query = IngredientQuery::by_name("eggplant");
+        let ingredients = searcher.ingredients(query).unwrap();
+
+        assert_eq!(ingredients.len(), 1);
+        assert_eq!(ingredients[0].name, "Aubergine");
+        assert_eq!(ingredients[0].aliases, vec!["Eggplant"]);
+    }
+
+    #[test]
+    fn ingredients_by_tags() {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let tags = vec!["dairy".to_string(), "nuts-seeds".to_string()];
+        let mut ingredients = searcher
+            .ingredients(IngredientQuery::by_tags(&tags))
+            .unwrap();
+        ingredients.sort();
+
+        assert_eq!(
+            ingredients
+                .iter()
+                .map(|ingredient| ingredient.slug.as_str())
+                .collect::<Vec<_>>(),
+            vec!["butter", "milk", "peanut-butter"]
+        );
+        assert_eq!(ingredients[0].tags, vec!["dairy"]);
+    }
+
+    #[test]
+    fn ingredients_by_prefix_matches_aliases() {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let query = IngredientQuery::by_prefix("scal");
+        let ingredients = searcher.ingredients(query).unwrap();
+
+        assert_eq!(ingredients.len(), 1);
+        assert_eq!(ingredients[0].name, "Spring onion");
+    }
+
+    #[test]
+    fn ingredients_by_name_matches_plurals() {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+
+        let ingredients = searcher
+            .ingredients(IngredientQuery::by_name("eggs"))
+            .unwrap();
+        assert_eq!(ingredients.len(), 1);
+        assert_eq!(ingredients[0].name, "Egg");
+
+        let ingredients = searcher
+            .ingredients(IngredientQuery::by_name("tomato"))
+            .unwrap();
+        assert_eq!(ingredients.len(), 1);
+        assert_eq!(ingredients[0].name, "Tomatoes");
+    }
+
+    #[test]
+    fn ingredients_by_prefix_stemmed() {
+        let (recipes_index, ingredients_index) = setup_ingredients_index();
+
+        let searcher = Searcher::new(&recipes_index, &ingredients_index).unwrap();
+        let query = IngredientQuery::by_prefix("tomatoes");
+        let ingredients = searcher.ingredients(query).unwrap();
+
+        assert_eq!(
+            ingredients
+                .iter()
+                .map(|i| i.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Tomatoes", "Cherry tomato"]
+        );
+    }
+
     #[test]
     fn ingredients_by_prefix_butter() {
         let (recipes_index, ingredients_index) = setup_ingredients_index();
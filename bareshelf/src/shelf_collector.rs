@@ -0,0 +1,165 @@
+//! Shelf collector
+//!
+//! Plumbing shared by the collectors that count ingredients by how recipes overlap with a shelf.
+//! Each recipe's ingredients are read as facet ordinals and handed to a `ShelfTally` along with
+//! the ordinals of the shelf, the tally decides what to count.
+use std::collections::{HashMap, HashSet};
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::FacetReader,
+    schema::{Facet, Field},
+    DocId, Result, Score, SegmentOrdinal, SegmentReader, TantivyError,
+};
+
+/// What a `ShelfCollector` counts for each recipe
+pub(crate) trait ShelfTally: Clone + Send + Sync + 'static {
+    /// Count kept per ingredient
+    type Count: Default + Send + 'static;
+
+    /// Count a recipe made of the ingredients in `facet_ords`
+    fn collect(
+        &self,
+        facet_ords: &[u64],
+        shelf: &HashSet<u64>,
+        counts: &mut HashMap<u64, Self::Count>,
+    );
+
+    /// Add the count from another segment to the total
+    fn merge(total: &mut Self::Count, count: Self::Count);
+}
+
+pub(crate) struct ShelfCollector<T> {
+    field: Field,
+    shelf: Vec<Facet>,
+    tally: T,
+}
+
+pub(crate) struct ShelfSegmentCollector<T: ShelfTally> {
+    reader: FacetReader,
+    shelf: HashSet<u64>,
+    tally: T,
+    counts: HashMap<u64, T::Count>,
+    facet_ords_buf: Vec<u64>,
+}
+
+impl<T> ShelfCollector<T> {
+    pub fn with_tally(field: Field, shelf: Vec<Facet>, tally: T) -> Self {
+        Self {
+            field,
+            shelf,
+            tally,
+        }
+    }
+}
+
+/// The facet reader of a segment and the ordinals of the shelf's facets in it
+///
+/// Facets that are not in the segment are left out.
+pub(crate) fn shelf_facet_reader(
+    reader: &SegmentReader,
+    field: Field,
+    shelf: &[Facet],
+) -> Result<(FacetReader, HashSet<u64>)> {
+    let field_name = reader.schema().get_field_name(field);
+    let facet_reader = reader.facet_reader(field).map_err(|_| {
+        TantivyError::SchemaError(format!("Field {:?} is not a facet field.", field_name))
+    })?;
+    let facet_dict = facet_reader.facet_dict();
+    let ords = shelf
+        .iter()
+        .filter_map(|key| {
+            facet_dict
+                .term_ord(key.encoded_str())
+                .expect("IO error here implies the index is borked")
+        })
+        .collect();
+
+    Ok((facet_reader, ords))
+}
+
+impl<T: ShelfTally> Collector for ShelfCollector<T> {
+    type Fruit = HashMap<Facet, T::Count>;
+    type Child = ShelfSegmentCollector<T>;
+
+    fn for_segment(&self, _: SegmentOrdinal, reader: &SegmentReader) -> Result<Self::Child> {
+        let (facet_reader, shelf) = shelf_facet_reader(reader, self.field, &self.shelf)?;
+
+        Ok(ShelfSegmentCollector {
+            reader: facet_reader,
+            shelf,
+            tally: self.tally.clone(),
+            counts: HashMap::new(),
+            facet_ords_buf: Vec::with_capacity(255),
+        })
+    }
+
+    fn merge_fruits(&self, segments_facet_counts: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        let mut facet_counts = HashMap::new();
+        for segment_facet_counts in segments_facet_counts {
+            for (facet, count) in segment_facet_counts {
+                T::merge(facet_counts.entry(facet).or_default(), count);
+            }
+        }
+        Ok(facet_counts)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+}
+
+impl<T: ShelfTally> SegmentCollector for ShelfSegmentCollector<T> {
+    type Fruit = HashMap<Facet, T::Count>;
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        self.reader.facet_ords(doc, &mut self.facet_ords_buf);
+        self.tally
+            .collect(&self.facet_ords_buf, &self.shelf, &mut self.counts);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        let facet_dict = self.reader.facet_dict();
+        self.counts
+            .into_iter()
+            .map(|(ord, count)| {
+                let mut facet = vec![];
+                facet_dict
+                    .ord_to_term(ord, &mut facet)
+                    .expect("IO error here implies the index is borked");
+                (Facet::from_encoded(facet).unwrap(), count)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tantivy::{
+        doc,
+        schema::{Facet, Field, Schema, TEXT},
+        Index,
+    };
+
+    /// An index of recipes, each a name and its ingredient slugs
+    pub(crate) fn index_recipes(recipes: &[(&str, &[&str])]) -> (Index, Field) {
+        let mut schema_builder = Schema::builder();
+
+        let name = schema_builder.add_text_field("name", TEXT);
+        let ingredient = schema_builder.add_facet_field("ingredient", ());
+
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+
+        let mut writer = index.writer(30_000_000).unwrap();
+        for (recipe_name, slugs) in recipes {
+            let mut document = doc!(name => *recipe_name);
+            for slug in slugs.iter() {
+                document.add_facet(ingredient, Facet::from(&format!("/ingredient/{}", slug)));
+            }
+            writer.add_document(document).unwrap();
+        }
+        writer.commit().unwrap();
+
+        (index, ingredient)
+    }
+}
@@ -51,6 +51,7 @@ impl From<Recipe> for BareshelfRecipe {
             chef_name: recipe.chef_name,
             image_name: recipe.image_name,
             ingredients: recipe.ingredients.iter().cloned().map(Into::into).collect(),
+            allergens: vec![],
         }
     }
 }
@@ -79,8 +80,11 @@ impl Recipe {
         self.image_name = Some(image_name);
     }
 
-    pub fn add_ingredient(&mut self, name: String, slug: String) {
-        self.ingredients.push(Ingredient { name, slug });
+    /// Add an ingredient, with its tags so the recipe's allergens can be worked out
+    pub fn add_ingredient(&mut self, name: String, slug: String, tags: Option<Vec<String>>) {
+        let mut ingredient = Ingredient::new(name, slug);
+        ingredient.tags = tags.unwrap_or_default();
+        self.ingredients.push(ingredient);
     }
 }
 
@@ -89,6 +93,8 @@ impl Recipe {
 struct Ingredient {
     name: String,
     slug: String,
+    aliases: Vec<String>,
+    tags: Vec<String>,
 }
 
 impl From<Ingredient> for BareshelfIngredient {
@@ -96,6 +102,8 @@ impl From<Ingredient> for BareshelfIngredient {
         BareshelfIngredient {
             name: ingredient.name,
             slug: ingredient.slug,
+            aliases: ingredient.aliases,
+            tags: ingredient.tags,
         }
     }
 }
@@ -104,7 +112,20 @@ impl From<Ingredient> for BareshelfIngredient {
 impl Ingredient {
     #[new]
     fn new(name: String, slug: String) -> Self {
-        Self { name, slug }
+        Self {
+            name,
+            slug,
+            aliases: vec![],
+            tags: vec![],
+        }
+    }
+
+    pub fn add_alias(&mut self, alias: String) {
+        self.aliases.push(alias);
+    }
+
+    pub fn add_tag(&mut self, tag: String) {
+        self.tags.push(tag);
     }
 }
 
@@ -0,0 +1,232 @@
+//! Optional user accounts
+//!
+//! An account links an email address and password to a shelf uid so the same shelf can be used
+//! from several devices. Accounts are stored in their own sled tree keyed by email address.
+use std::num::NonZeroU32;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, households::MemberId, shelf::Uid};
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = ring::digest::SHA256_OUTPUT_LEN;
+pub(crate) const MIN_PASSWORD_LEN: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Account {
+    pub(crate) email: String,
+    pub(crate) uid: Uid,
+    password_hash: String,
+}
+
+impl Account {
+    /// The member id the account uses in households, see `account_member`
+    pub(crate) fn member(&self) -> MemberId {
+        account_member(&self.email)
+    }
+}
+
+/// The member id of an account, the same on every device it is logged in from
+///
+/// Accounts are keyed by email address so the id is derived from it rather than stored.
+pub(crate) fn account_member(email: &str) -> MemberId {
+    let digest = ring::digest::digest(&ring::digest::SHA256, email.as_bytes());
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&digest.as_ref()[..16]);
+    MemberId::from_be_bytes(id)
+}
+
+pub(crate) struct Accounts {
+    tree: sled::Tree,
+}
+
+impl Accounts {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            tree: sled.open_tree("accounts")?,
+        })
+    }
+
+    /// Create an account that owns the shelf `uid`
+    pub(crate) fn create(&self, email: &str, password: &str, uid: Uid) -> Result<Account, Error> {
+        let email = normalise_email(email)?;
+        if password.len() < MIN_PASSWORD_LEN {
+            return Err(Error::Other(format!(
+                "Passwords must be at least {} characters",
+                MIN_PASSWORD_LEN
+            )));
+        }
+        let account = Account {
+            email,
+            uid,
+            password_hash: hash_password(password)?,
+        };
+
+        let created = self.tree.compare_and_swap(
+            account.email.as_bytes(),
+            None as Option<&[u8]>,
+            Some(serde_json::to_vec(&account)?),
+        )?;
+        if created.is_err() {
+            return Err(Error::Other(format!(
+                "An account already exists for {}",
+                account.email
+            )));
+        }
+        Ok(account)
+    }
+
+    /// Find the account for `email` if the password is correct
+    pub(crate) fn authenticate(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<Option<Account>, Error> {
+        let account = match self.get(&normalise_email(email)?)? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        if verify_password(&account.password_hash, password) {
+            Ok(Some(account))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get(&self, email: &str) -> Result<Option<Account>, Error> {
+        match self.tree.get(email.as_bytes())? {
+            Some(account) => Ok(Some(serde_json::from_slice(&account)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl FromRequest for Accounts {
+    type Error = Error;
+    type Future = Ready<Result<Accounts, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
+        ready(Accounts::new(sled.get_ref()))
+    }
+}
+
+fn normalise_email(email: &str) -> Result<String, Error> {
+    let email = email.trim().to_lowercase();
+    if email.len() < 3 || !email.contains('@') {
+        return Err(Error::Other(format!(
+            "{} is not a valid email address",
+            email
+        )));
+    }
+    Ok(email)
+}
+
+/// Hash a password with PBKDF2 into `pbkdf2-sha256$<iterations>$<salt>$<hash>`
+fn hash_password(password: &str) -> Result<String, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| Error::Other("failed to generate salt".to_string()))?;
+    let mut hash = [0u8; HASH_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+
+    Ok(format!(
+        "pbkdf2-sha256${}${}${}",
+        PBKDF2_ITERATIONS,
+        base64::encode_config(salt, base64::URL_SAFE_NO_PAD),
+        base64::encode_config(hash, base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+fn verify_password(password_hash: &str, password: &str) -> bool {
+    let parts: Vec<_> = password_hash.split('$').collect();
+    if parts.len() != 4 || parts[0] != "pbkdf2-sha256" {
+        return false;
+    }
+    let (iterations, salt, hash) = match (
+        parts[1].parse().ok().and_then(NonZeroU32::new),
+        base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD),
+        base64::decode_config(parts[3], base64::URL_SAFE_NO_PAD),
+    ) {
+        (Some(iterations), Ok(salt), Ok(hash)) => (iterations, salt, hash),
+        _ => return false,
+    };
+
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts() -> Accounts {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        Accounts::new(&sled).unwrap()
+    }
+
+    #[test]
+    fn hash_and_verify_a_password() {
+        let hash = hash_password("a password").unwrap();
+
+        assert!(hash.starts_with("pbkdf2-sha256$"));
+        assert!(verify_password(&hash, "a password"));
+        assert!(!verify_password(&hash, "another password"));
+        assert_ne!(hash, hash_password("a password").unwrap());
+    }
+
+    #[test]
+    fn create_and_authenticate() {
+        let accounts = accounts();
+        accounts
+            .create("Someone@Example.org ", "a password", 1234)
+            .unwrap();
+
+        let account = accounts
+            .authenticate("someone@example.org", "a password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.uid, 1234);
+        assert!(accounts
+            .authenticate("someone@example.org", "wrong password")
+            .unwrap()
+            .is_none());
+        assert!(accounts
+            .authenticate("nobody@example.org", "a password")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn reject_duplicate_accounts() {
+        let accounts = accounts();
+        accounts
+            .create("someone@example.org", "a password", 1)
+            .unwrap();
+
+        assert!(accounts
+            .create("someone@example.org", "a password", 2)
+            .is_err());
+        assert_eq!(accounts.get("someone@example.org").unwrap().unwrap().uid, 1);
+    }
+}
@@ -0,0 +1,61 @@
+//! Bulk import of ingredients from a pasted list
+//!
+use std::collections::HashSet;
+
+/// Split a pasted shopping list into the ingredients it names
+///
+/// Items can be one per line or comma-separated. Bullets and leading quantities such as
+/// "2 x" or "500g" are dropped, as are duplicates.
+pub(crate) fn parse_list(input: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    input
+        .split(['\n', ',', ';'])
+        .map(clean_item)
+        .filter(|item| !item.is_empty())
+        .filter(|item| seen.insert(item.to_lowercase()))
+        .collect()
+}
+
+fn clean_item(item: &str) -> String {
+    let item = item
+        .trim()
+        .trim_start_matches(|c: char| c == '-' || c == '*' || c == '•' || c.is_whitespace());
+
+    let mut words = item.split_whitespace().peekable();
+    while let Some(word) = words.peek() {
+        if is_quantity(word) {
+            words.next();
+        } else {
+            break;
+        }
+    }
+    words.collect::<Vec<_>>().join(" ")
+}
+
+/// Whether a word is a quantity, eg. "2", "2x", "x", "500g", "1.5kg"
+fn is_quantity(word: &str) -> bool {
+    let word = word.to_lowercase();
+    if word == "x" {
+        return true;
+    }
+    let unit = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '/');
+    unit.len() < word.len() && ["", "x", "g", "kg", "ml", "l", "oz", "lb", "lbs"].contains(&unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lines_and_commas() {
+        assert_eq!(
+            parse_list("eggs\n- milk, butter\n\n* 2 x tins of tomatoes\n500g flour;Eggs"),
+            vec!["eggs", "milk", "butter", "tins of tomatoes", "flour"]
+        );
+    }
+
+    #[test]
+    fn keep_words_that_start_with_numbers() {
+        assert_eq!(parse_list("5-spice powder"), vec!["5-spice powder"]);
+    }
+}
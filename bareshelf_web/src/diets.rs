@@ -0,0 +1,168 @@
+//! Dietary profiles
+//!
+//! A diet rules out every ingredient with one of its tags. Ingredients inherit the tags of their
+//! parents when they are indexed, so a diet ruling out meat also rules out "chicken thigh" when
+//! only "chicken" is tagged. A shelf can keep to several diets; their banned ingredients are
+//! added to the ones banned by hand when searching.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use bareshelf::{IngredientQuery, Searcher};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Diet {
+    Vegetarian,
+    Vegan,
+    GlutenFree,
+    NutFree,
+}
+
+impl Diet {
+    pub(crate) const ALL: [Diet; 4] = [
+        Diet::Vegetarian,
+        Diet::Vegan,
+        Diet::GlutenFree,
+        Diet::NutFree,
+    ];
+
+    pub(crate) fn slug(&self) -> &'static str {
+        match self {
+            Diet::Vegetarian => "vegetarian",
+            Diet::Vegan => "vegan",
+            Diet::GlutenFree => "gluten-free",
+            Diet::NutFree => "nut-free",
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Diet::Vegetarian => "vegetarian",
+            Diet::Vegan => "vegan",
+            Diet::GlutenFree => "gluten free",
+            Diet::NutFree => "nut free",
+        }
+    }
+
+    /// Tags of the ingredients the diet rules out
+    fn banned_tags(&self) -> &'static [&'static str] {
+        match self {
+            Diet::Vegetarian => &["meat", "fish", "seafood"],
+            Diet::Vegan => &["meat", "fish", "seafood", "dairy", "eggs", "honey"],
+            Diet::GlutenFree => &["gluten"],
+            // seeds share a tag with nuts, better to rule out too much than too little
+            Diet::NutFree => &["nuts-seeds"],
+        }
+    }
+}
+
+impl std::str::FromStr for Diet {
+    type Err = Error;
+
+    fn from_str(slug: &str) -> Result<Self, Self::Err> {
+        Diet::ALL
+            .iter()
+            .find(|diet| diet.slug() == slug)
+            .copied()
+            .ok_or_else(|| Error::Other(format!("unknown diet {}", slug)))
+    }
+}
+
+/// The ingredients each diet rules out
+///
+/// Diets without any tagged ingredients in the index are left out, nothing can be said about
+/// whether a recipe keeps to them.
+pub(crate) struct DietBans {
+    banned: Vec<(Diet, HashSet<String>)>,
+}
+
+impl DietBans {
+    pub(crate) fn new(searcher: &Searcher) -> bareshelf::Result<Self> {
+        let mut banned = vec![];
+        for diet in Diet::ALL {
+            let tags: Vec<String> = diet.banned_tags().iter().map(|t| t.to_string()).collect();
+            let slugs: HashSet<String> = searcher
+                .ingredients(IngredientQuery::by_tags(&tags))?
+                .into_iter()
+                .map(|ingredient| ingredient.slug)
+                .collect();
+            if !slugs.is_empty() {
+                banned.push((diet, slugs));
+            }
+        }
+        Ok(Self { banned })
+    }
+
+    /// Slugs of the ingredients ruled out by any of the diets
+    pub(crate) fn banned(&self, diets: &[Diet]) -> Vec<String> {
+        let mut slugs: Vec<String> = self
+            .banned
+            .iter()
+            .filter(|(diet, _)| diets.contains(diet))
+            .flat_map(|(_, slugs)| slugs.iter().cloned())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        slugs.sort();
+        slugs
+    }
+
+    /// The diets a recipe made of these ingredients keeps to
+    pub(crate) fn kept_by<'a>(&self, slugs: impl Iterator<Item = &'a str> + Clone) -> Vec<Diet> {
+        self.banned
+            .iter()
+            .filter(|(_, banned)| !slugs.clone().any(|slug| banned.contains(slug)))
+            .map(|(diet, _)| *diet)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bans() -> DietBans {
+        let slugs = |slugs: &[&str]| slugs.iter().map(|slug| slug.to_string()).collect();
+        DietBans {
+            banned: vec![
+                (Diet::Vegetarian, slugs(&["bacon", "salmon"])),
+                (Diet::Vegan, slugs(&["bacon", "salmon", "egg", "milk"])),
+                (Diet::NutFree, slugs(&["walnut"])),
+            ],
+        }
+    }
+
+    #[test]
+    fn combine_banned_ingredients() {
+        assert_eq!(
+            bans().banned(&[Diet::Vegan, Diet::NutFree, Diet::GlutenFree]),
+            vec!["bacon", "egg", "milk", "salmon", "walnut"]
+        );
+        assert!(bans().banned(&[]).is_empty());
+    }
+
+    #[test]
+    fn recipes_keep_to_diets_without_their_ingredients() {
+        let bans = bans();
+
+        assert_eq!(
+            bans.kept_by(["egg", "milk", "flour"].iter().copied()),
+            vec![Diet::Vegetarian, Diet::NutFree]
+        );
+        assert_eq!(
+            bans.kept_by(["bacon", "walnut"].iter().copied()),
+            Vec::<Diet>::new()
+        );
+    }
+
+    #[test]
+    fn parse_diets() {
+        for diet in Diet::ALL {
+            assert_eq!(diet.slug().parse::<Diet>().unwrap(), diet);
+        }
+        assert!("pescatarian".parse::<Diet>().is_err());
+    }
+}
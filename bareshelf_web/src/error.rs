@@ -2,16 +2,22 @@ use std::fmt;
 
 #[derive(Debug)]
 pub enum Error {
+    Csv(csv::Error),
     SerdeJson(serde_json::Error),
     Sled(sled::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
     Other(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::Csv(ref e) => e.fmt(f),
             Error::SerdeJson(ref e) => e.fmt(f),
             Error::Sled(ref e) => e.fmt(f),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(ref e) => e.fmt(f),
             Error::Other(ref s) => f.write_str(s),
         }
     }
@@ -26,8 +32,21 @@ impl From<sled::Error> for Error {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Sqlite(err)
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Error {
         Error::SerdeJson(err)
     }
 }
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
@@ -3,14 +3,20 @@ use actix_web::body::BoxBody;
 use actix_web::{dev::Payload, FromRequest, HttpRequest, HttpResponse};
 use actix_web::{error, Error, Responder};
 use futures::future::{err, ok, Ready};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
-pub(crate) struct FlashMessage(Option<String>);
+pub(crate) struct FlashMessage(Option<String>, Option<UndoHide>);
 
 impl FlashMessage {
     pub fn take(self) -> Option<String> {
         self.0
     }
+
+    /// The recipe that was just hidden, if any
+    pub fn undo_hide(&self) -> Option<&UndoHide> {
+        self.1.as_ref()
+    }
 }
 
 impl FromRequest for FlashMessage {
@@ -19,17 +25,28 @@ impl FromRequest for FlashMessage {
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let session = req.get_session();
-        if let Ok(flash) = session.get("flash") {
+        if let (Ok(flash), Ok(undo_hide)) = (session.get("flash"), session.get("undo_hide")) {
             session.remove("flash");
-            ok(FlashMessage(flash))
+            session.remove("undo_hide");
+            ok(FlashMessage(flash, undo_hide))
         } else {
             err(error::ErrorBadRequest("Unable to read flash message"))
         }
     }
 }
 
+/// A recipe that was just hidden and the page to go back to after undoing it
+///
+/// Like the flash message it is only offered on the next page.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UndoHide {
+    pub slug: String,
+    pub redirect: String,
+}
+
 pub(crate) struct FlashResponse {
     message: Option<String>,
+    undo_hide: Option<UndoHide>,
     location: String,
 }
 
@@ -37,9 +54,16 @@ impl FlashResponse {
     pub fn new(message: Option<String>, location: &str) -> Self {
         FlashResponse {
             message,
+            undo_hide: None,
             location: location.to_owned(),
         }
     }
+
+    /// Offer to undo hiding a recipe along with the message
+    pub fn with_undo_hide(mut self, undo_hide: UndoHide) -> Self {
+        self.undo_hide = Some(undo_hide);
+        self
+    }
 }
 
 impl Responder for FlashResponse {
@@ -47,15 +71,27 @@ impl Responder for FlashResponse {
 
     fn respond_to(self, req: &HttpRequest) -> HttpResponse {
         let session = req.get_session();
+        let FlashResponse {
+            message,
+            undo_hide,
+            location,
+        } = self;
         let set_flash = session
-            .insert("flash", self.message)
+            .insert("flash", message)
+            .and_then(|_| match undo_hide {
+                Some(undo_hide) => session.insert("undo_hide", undo_hide),
+                None => {
+                    session.remove("undo_hide");
+                    Ok(())
+                }
+            })
             .map_err(|_| error::ErrorInternalServerError("failed to set flash"));
         if let Err(err) = set_flash {
             return HttpResponse::from(err);
         }
 
         let responder = HttpResponse::SeeOther()
-            .append_header((actix_web::http::header::LOCATION, self.location))
+            .append_header((actix_web::http::header::LOCATION, location))
             .finish();
 
         responder.respond_to(req)
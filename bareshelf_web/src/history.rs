@@ -0,0 +1,68 @@
+//! Shelf history
+//!
+//! Every change to a shelf is appended to a log in its `ShelfStore` so that changes can be
+//! reviewed and the last one undone. Entries older than `MAX_AGE` or beyond the newest
+//! `MAX_ENTRIES` are pruned whenever a new entry is added.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use bareshelf::Ingredient;
+use chrono::NaiveDate;
+
+use crate::{households::MemberId, shelf::Bucket};
+
+/// How long history is kept for, in seconds
+pub(crate) const MAX_AGE: u64 = 30 * 24 * 60 * 60;
+/// How many entries are kept for each shelf
+pub(crate) const MAX_ENTRIES: usize = 200;
+
+/// What happened to one bucket in a change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BucketChange {
+    pub(crate) bucket: Bucket,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) added: Vec<Ingredient>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) removed: Vec<Ingredient>,
+    /// Best before dates the removed ingredients had, restored if the change is undone
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) best_before: HashMap<String, NaiveDate>,
+}
+
+impl BucketChange {
+    pub(crate) fn added(bucket: Bucket, added: Vec<Ingredient>) -> Self {
+        Self {
+            bucket,
+            added,
+            removed: vec![],
+            best_before: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn removed(
+        bucket: Bucket,
+        removed: Vec<Ingredient>,
+        best_before: HashMap<String, NaiveDate>,
+    ) -> Self {
+        Self {
+            bucket,
+            added: vec![],
+            removed,
+            best_before,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A single action on a shelf, which may touch several buckets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    pub(crate) member: MemberId,
+    /// Unix timestamp in seconds
+    pub(crate) at: u64,
+    pub(crate) changes: Vec<BucketChange>,
+}
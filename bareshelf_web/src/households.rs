@@ -0,0 +1,333 @@
+//! Households
+//!
+//! A household is a shelf used by several people. The household id is the uid of the shelf and
+//! the owner is whoever first created a join link for it. Members can leave a household, which
+//! takes them back to the shelf they were using before they joined and leaves the household's
+//! shelf as it is.
+use actix_session::Session;
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{accounts::account_member, error::Error, shelf::Uid};
+
+/// Identifies a person using a shelf
+///
+/// Several sessions share a uid once they join a household, so each session also gets its own
+/// member id, or uses its account's when logged in.
+pub(crate) type MemberId = u128;
+
+/// The member id of the session, creating one if needed
+///
+/// Sessions logged in to an account use the account's member id, so the account is the same
+/// member of a household whichever device it is used from.
+pub(crate) fn session_member(session: &Session) -> MemberId {
+    if let Ok(Some(email)) = session.get::<String>("account") {
+        return account_member(&email);
+    }
+    session.get("member").unwrap_or(None).unwrap_or_else(|| {
+        let member = rand::thread_rng().gen::<MemberId>();
+        session.insert("member", member).unwrap();
+        member
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Member {
+    pub(crate) id: MemberId,
+    pub(crate) name: String,
+    /// The shelf the member was using before they joined, restored when they leave
+    pub(crate) home: Uid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Household {
+    pub(crate) id: Uid,
+    pub(crate) owner: MemberId,
+    /// Everyone using the shelf, including the owner
+    pub(crate) members: Vec<Member>,
+}
+
+impl Household {
+    pub(crate) fn member(&self, id: MemberId) -> Option<&Member> {
+        self.members.iter().find(|member| member.id == id)
+    }
+
+    /// The name to show for a member, who may have since left
+    pub(crate) fn name_of(&self, id: MemberId) -> &str {
+        self.member(id)
+            .map(|member| member.name.as_str())
+            .unwrap_or("a former member")
+    }
+
+    pub(crate) fn is_shared(&self) -> bool {
+        self.members.len() > 1
+    }
+}
+
+pub(crate) struct Households {
+    tree: sled::Tree,
+}
+
+impl Households {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            tree: sled.open_tree("households")?,
+        })
+    }
+
+    pub(crate) fn get(&self, id: Uid) -> Result<Option<Household>, Error> {
+        match self.tree.get(id.to_be_bytes())? {
+            Some(household) => Ok(Some(serde_json::from_slice(&household)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the household for a shelf, creating it with `owner` as its owner if needed
+    pub(crate) fn get_or_create(
+        &self,
+        id: Uid,
+        owner: MemberId,
+        name: &str,
+    ) -> Result<Household, Error> {
+        self.update(id, |household| {
+            Ok(household
+                .get_or_insert_with(|| Household {
+                    id,
+                    owner,
+                    members: vec![Member {
+                        id: owner,
+                        name: name.to_string(),
+                        home: id,
+                    }],
+                })
+                .clone())
+        })
+    }
+
+    /// Add a member to a household
+    ///
+    /// `from` is the shelf the member is using now. If that is another household they are a
+    /// member of they leave it first, keeping the shelf they were using before that as their home.
+    pub(crate) fn join(
+        &self,
+        id: Uid,
+        member: MemberId,
+        name: &str,
+        from: Uid,
+    ) -> Result<Household, Error> {
+        let no_longer_shared =
+            || Error::Other("This shelf is no longer shared, ask for a new link".to_string());
+        let household = self.get(id)?.ok_or_else(no_longer_shared)?;
+        if household.member(member).is_some() {
+            return Ok(household);
+        }
+
+        let home = match self.get(from)? {
+            Some(previous) if previous.owner != member => self
+                .leave(from, member)?
+                .map(|previous| previous.home)
+                .unwrap_or(from),
+            _ => from,
+        };
+        self.update(id, |household| {
+            let household = household.as_mut().ok_or_else(no_longer_shared)?;
+            if household.member(member).is_none() {
+                household.members.push(Member {
+                    id: member,
+                    name: name.to_string(),
+                    home,
+                });
+            }
+            Ok(household.clone())
+        })
+    }
+
+    /// Remove a member from a household, keeping its shelf
+    ///
+    /// Returns the member that left so they can be sent back to their home shelf, or `None` if
+    /// they were not a member. Owners cannot leave their own household.
+    pub(crate) fn leave(&self, id: Uid, member: MemberId) -> Result<Option<Member>, Error> {
+        self.update(id, |household| {
+            let household = match household {
+                Some(household) => household,
+                None => return Ok(None),
+            };
+            if household.owner == member {
+                return Err(Error::Other(
+                    "You own this shelf so you can't leave it".to_string(),
+                ));
+            }
+            Ok(household
+                .members
+                .iter()
+                .position(|m| m.id == member)
+                .map(|position| household.members.remove(position)))
+        })
+    }
+
+    /// Carry a member's place in a household over to a new member id
+    pub(crate) fn rename_member(&self, id: Uid, from: MemberId, to: MemberId) -> Result<(), Error> {
+        self.update(id, |household| {
+            if let Some(household) = household {
+                if household.owner == from {
+                    household.owner = to;
+                }
+                for member in household
+                    .members
+                    .iter_mut()
+                    .filter(|member| member.id == from)
+                {
+                    member.id = to;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Change a household, trying again if someone else changed it first
+    fn update<T>(
+        &self,
+        id: Uid,
+        mut f: impl FnMut(&mut Option<Household>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let key = id.to_be_bytes();
+        loop {
+            let stored = self.tree.get(key)?;
+            let mut household = match &stored {
+                Some(household) => Some(serde_json::from_slice(household)?),
+                None => None,
+            };
+            let result = f(&mut household)?;
+            let new = household.as_ref().map(serde_json::to_vec).transpose()?;
+            if new.as_deref() == stored.as_deref()
+                || self.tree.compare_and_swap(key, stored, new)?.is_ok()
+            {
+                return Ok(result);
+            }
+        }
+    }
+}
+
+impl FromRequest for Households {
+    type Error = Error;
+    type Future = Ready<Result<Households, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
+        ready(Households::new(sled.get_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn households() -> Households {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        Households::new(&sled).unwrap()
+    }
+
+    #[test]
+    fn join_and_leave_a_household() {
+        let households = households();
+        households.get_or_create(1, 10, "Owner").unwrap();
+
+        let household = households.join(1, 20, "Guest", 2).unwrap();
+        assert!(household.is_shared());
+        assert_eq!(household.name_of(20), "Guest");
+
+        assert!(households.leave(1, 10).is_err());
+        let member = households.leave(1, 20).unwrap().unwrap();
+        assert_eq!(member.home, 2);
+        assert!(!households.get(1).unwrap().unwrap().is_shared());
+        assert!(households.leave(1, 20).unwrap().is_none());
+    }
+
+    #[test]
+    fn joining_another_household_keeps_the_original_home() {
+        let households = households();
+        households.get_or_create(1, 10, "First owner").unwrap();
+        households.get_or_create(2, 20, "Second owner").unwrap();
+
+        households.join(1, 30, "Guest", 3).unwrap();
+        households.join(2, 30, "Guest", 1).unwrap();
+
+        assert!(households.get(1).unwrap().unwrap().member(30).is_none());
+        assert_eq!(
+            households.get(2).unwrap().unwrap().member(30).unwrap().home,
+            3
+        );
+    }
+
+    #[test]
+    fn rename_a_member() {
+        let households = households();
+        households.get_or_create(1, 10, "Owner").unwrap();
+        households.join(1, 20, "Guest", 2).unwrap();
+
+        households.rename_member(1, 10, 11).unwrap();
+        households.rename_member(1, 20, 21).unwrap();
+
+        let household = households.get(1).unwrap().unwrap();
+        assert_eq!(household.owner, 11);
+        assert_eq!(household.name_of(21), "Guest");
+        assert!(household.member(20).is_none());
+    }
+
+    #[test]
+    fn cannot_join_an_unshared_shelf() {
+        assert!(households().join(1, 20, "Guest", 2).is_err());
+    }
+
+    #[test]
+    fn concurrent_joins_and_leaves_are_not_lost() {
+        let households = std::sync::Arc::new(households());
+        households.get_or_create(1, 1, "Owner").unwrap();
+
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let households = households.clone();
+                std::thread::spawn(move || {
+                    let members: Vec<_> = (0..20).map(|i| 1000 * (thread + 1) + i).collect();
+                    for member in &members {
+                        households.join(1, *member, "Guest", 2).unwrap();
+                    }
+                    for member in members.iter().step_by(2) {
+                        assert!(households.leave(1, *member).unwrap().is_some());
+                    }
+                    households
+                        .rename_member(1, members[1], members[1] + 100)
+                        .unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut members: Vec<_> = households
+            .get(1)
+            .unwrap()
+            .unwrap()
+            .members
+            .iter()
+            .map(|member| member.id)
+            .collect();
+        let mut expected: Vec<_> = (0..8)
+            .flat_map(|thread| (1..20).step_by(2).map(move |i| 1000 * (thread + 1) + i))
+            .map(|member| {
+                if member % 1000 == 1 {
+                    member + 100
+                } else {
+                    member
+                }
+            })
+            .chain(Some(1))
+            .collect();
+        members.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(members, expected);
+    }
+}
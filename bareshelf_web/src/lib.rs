@@ -1,14 +1,23 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use actix_session::{storage::CookieSessionStore, SessionMiddleware};
 use actix_web::{cookie::Key, middleware::Logger, web, App, HttpServer};
 use tera::{Result as TeraResult, Tera};
 
+mod accounts;
+mod bulk_import;
+mod diets;
 mod error;
 mod flash;
+mod history;
+mod households;
+mod recipe_lists;
 mod routes;
 mod sharing;
 mod shelf;
+mod shelf_file;
+mod shelf_store;
+mod shopping_list;
 mod views;
 
 #[cfg(feature = "embedded-templates")]
@@ -25,10 +34,40 @@ fn templates() -> TeraResult<Tera> {
             "includes/nav-links.html",
             include_str!("../templates/includes/nav-links.html"),
         ),
+        (
+            "includes/undo-hide.html",
+            include_str!("../templates/includes/undo-hide.html"),
+        ),
         (
             "share-shelf.html",
             include_str!("../templates/share-shelf.html"),
         ),
+        (
+            "choose-ingredient.html",
+            include_str!("../templates/choose-ingredient.html"),
+        ),
+        ("account.html", include_str!("../templates/account.html")),
+        ("history.html", include_str!("../templates/history.html")),
+        (
+            "favourites.html",
+            include_str!("../templates/favourites.html"),
+        ),
+        (
+            "use-it-up.html",
+            include_str!("../templates/use-it-up.html"),
+        ),
+        (
+            "shared-shelf.html",
+            include_str!("../templates/shared-shelf.html"),
+        ),
+        (
+            "import-shelf.html",
+            include_str!("../templates/import-shelf.html"),
+        ),
+        (
+            "import-shelf-review.html",
+            include_str!("../templates/import-shelf-review.html"),
+        ),
     ];
     match Tera::new("/dev/null/*") {
         Ok(mut tera) => {
@@ -44,13 +83,29 @@ fn templates() -> TeraResult<Tera> {
     Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*"))
 }
 
+/// Choose where shelves are stored with `SHELF_STORE`, see `shelf_store`
+fn shelf_store(sled: &sled::Db) -> Arc<dyn shelf_store::ShelfStore> {
+    match std::env::var("SHELF_STORE").as_deref().unwrap_or("sled") {
+        "sled" => Arc::new(shelf_store::SledShelfStore::new(sled).unwrap()),
+        "memory" => Arc::new(shelf_store::MemoryShelfStore::default()),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Arc::new(
+            shelf_store::SqliteShelfStore::open(Path::new(
+                &std::env::var("SQLITE_PATH").unwrap_or_else(|_| "./shelves.sqlite3".to_string()),
+            ))
+            .expect("Could not open SQLite database"),
+        ),
+        other => panic!("Unknown SHELF_STORE {}", other),
+    }
+}
+
 pub(crate) struct AppData {
     cookie_key: Key,
 }
 
 pub async fn run_server() -> std::io::Result<()> {
     let cookie_key = Key::from(
-        &base64::decode(&std::env::var("COOKIE_SECRET").expect("COOKIE_SECRET is required"))
+        &base64::decode(std::env::var("COOKIE_SECRET").expect("COOKIE_SECRET is required"))
             .expect("COOKIE_SECRET is not valid base64"),
     );
     let app_host = std::env::var("APP_HOST").expect("APP_HOST must be set");
@@ -60,13 +115,15 @@ pub async fn run_server() -> std::io::Result<()> {
     ))
     .expect("Could not open search index");
     let sled =
-        sled::open(&std::env::var("SLED_PATH").unwrap_or_else(|_| "./sled".to_string())).unwrap();
+        sled::open(std::env::var("SLED_PATH").unwrap_or_else(|_| "./sled".to_string())).unwrap();
+    let shelf_store = shelf_store(&sled);
 
     HttpServer::new(move || {
         let tera = tera.clone();
         let cookie_key = cookie_key.clone();
         let searcher = searcher.clone();
         let sled = sled.clone();
+        let shelf_store = web::Data::from(shelf_store.clone());
 
         App::new()
             .wrap(Logger::default())
@@ -74,21 +131,71 @@ pub async fn run_server() -> std::io::Result<()> {
                 CookieSessionStore::default(),
                 cookie_key.clone(),
             ))
-            .app_data(AppData { cookie_key })
-            .app_data(tera)
-            .app_data(searcher)
-            .app_data(sled)
+            .app_data(web::Data::new(AppData { cookie_key }))
+            .app_data(web::Data::new(tera))
+            .app_data(web::Data::new(searcher))
+            .app_data(web::Data::new(sled))
+            .app_data(shelf_store)
             .service(web::resource("/status").route(web::get().to(routes::status)))
             .service(
-                web::scope("/")
-                    .route("", web::get().to(routes::index))
+                web::scope("")
+                    .route("/", web::get().to(routes::index))
                     .route("/ingredients", web::get().to(routes::ingredients))
+                    .route("/ingredients/{slug}", web::get().to(routes::ingredient))
                     .route("/add-ingredient", web::post().to(routes::add_ingredient))
+                    .route("/pairings", web::get().to(routes::pairings))
                     .route(
                         "/remove-ingredient",
                         web::post().to(routes::remove_ingredient),
                     )
+                    .route("/history", web::get().to(routes::history))
+                    .route("/undo", web::post().to(routes::undo))
+                    .route("/use-it-up", web::get().to(routes::use_it_up))
+                    .route("/best-before", web::post().to(routes::set_best_before))
+                    .route("/diets", web::post().to(routes::set_diets))
+                    .route("/allergens", web::post().to(routes::set_allergens))
+                    .route(
+                        "/key-ingredient-mode",
+                        web::post().to(routes::set_key_ingredient_mode),
+                    )
+                    .route("/favourites", web::get().to(routes::favourites))
+                    .route("/favourite", web::post().to(routes::toggle_favourite))
+                    .route("/cooked", web::post().to(routes::cooked))
+                    .route("/hide-recipe", web::post().to(routes::hide_recipe))
+                    .route("/unhide-recipe", web::post().to(routes::unhide_recipe))
+                    .route("/meal-plan", web::get().to(routes::meal_plan))
+                    .route(
+                        "/meal-plan/shopping-list",
+                        web::post().to(routes::shop_for_meal_plan),
+                    )
+                    .route("/shopping-list", web::get().to(routes::shopping_list))
+                    .route(
+                        "/shopping-list/add",
+                        web::post().to(routes::add_to_shopping_list),
+                    )
+                    .route("/shopping-list/bought", web::post().to(routes::mark_bought))
+                    .route(
+                        "/shopping-list/remove",
+                        web::post().to(routes::remove_from_shopping_list),
+                    )
+                    .route("/import-shelf", web::get().to(routes::import_shelf))
+                    .route("/import-shelf", web::post().to(routes::review_import_shelf))
+                    .route(
+                        "/import-shelf/confirm",
+                        web::post().to(routes::confirm_import_shelf),
+                    )
+                    .route("/export-shelf", web::get().to(routes::export_shelf))
+                    .route("/upload-shelf", web::post().to(routes::upload_shelf))
                     .route("/share-shelf", web::get().to(routes::share_shelf))
+                    .route("/share-shelf", web::post().to(routes::create_share))
+                    .route("/share-shelf/revoke", web::post().to(routes::revoke_share))
+                    .route("/share-shelf/accept", web::post().to(routes::accept_share))
+                    .route("/household/leave", web::post().to(routes::leave_household))
+                    .route("/shared-shelf", web::get().to(routes::shared_shelf))
+                    .route("/account", web::get().to(routes::account))
+                    .route("/signup", web::post().to(routes::signup))
+                    .route("/login", web::post().to(routes::login))
+                    .route("/logout", web::post().to(routes::logout))
                     .route("/api/ingredients", web::get().to(routes::api_ingredients)),
             )
     })
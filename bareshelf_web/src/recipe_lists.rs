@@ -0,0 +1,264 @@
+//! Lists of favourite, cooked and hidden recipes
+//!
+//! Each shelf keeps its favourite recipes, a log of the recipes cooked from it and the recipes
+//! it never wants to see again. Recipes cooked in the last `COOKED_RECENTLY_DAYS` days are ranked
+//! lower when searching so the same dinner isn't suggested night after night.
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{error::Error, shelf::Uid};
+
+/// How long a cooked recipe is ranked lower for
+pub(crate) const COOKED_RECENTLY_DAYS: u64 = 14;
+/// How many cooked recipes are kept for each shelf
+const MAX_COOKED: usize = 100;
+
+/// A recipe saved to one of a shelf's lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedRecipe {
+    pub(crate) slug: String,
+    pub(crate) title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Cooked {
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    /// Unix timestamp in seconds
+    pub(crate) at: u64,
+}
+
+/// How much to demote a recipe cooked at `at`
+///
+/// From 2, as much as two missing ingredients, when just cooked down to 0 after
+/// `COOKED_RECENTLY_DAYS`.
+pub(crate) fn cooked_weight(at: u64, now: u64) -> f32 {
+    let days = now.saturating_sub(at) as f32 / (24 * 60 * 60) as f32;
+    (2.0 * (1.0 - days / COOKED_RECENTLY_DAYS as f32)).max(0.0)
+}
+
+pub(crate) struct RecipeLists {
+    favourites: sled::Tree,
+    cooked: sled::Tree,
+    hidden: sled::Tree,
+}
+
+impl RecipeLists {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            favourites: sled.open_tree("favourites")?,
+            cooked: sled.open_tree("cooked")?,
+            hidden: sled.open_tree("hidden_recipes")?,
+        })
+    }
+
+    /// Favourite recipes of a shelf, oldest first
+    pub(crate) fn favourites(&self, uid: Uid) -> Result<Vec<SavedRecipe>, Error> {
+        get(&self.favourites, uid)
+    }
+
+    /// Add a recipe to the favourites, or remove it if it is already there
+    ///
+    /// Returns whether the recipe is now a favourite.
+    pub(crate) fn toggle_favourite(
+        &self,
+        uid: Uid,
+        slug: &str,
+        title: &str,
+    ) -> Result<bool, Error> {
+        update(
+            &self.favourites,
+            uid,
+            |favourites: &mut Vec<SavedRecipe>| {
+                let position = favourites.iter().position(|f| f.slug == slug);
+                if let Some(position) = position {
+                    favourites.remove(position);
+                    return false;
+                }
+                favourites.push(SavedRecipe {
+                    slug: slug.to_string(),
+                    title: title.to_string(),
+                });
+                true
+            },
+        )
+    }
+
+    /// Recipes hidden from a shelf's search results, oldest first
+    pub(crate) fn hidden(&self, uid: Uid) -> Result<Vec<SavedRecipe>, Error> {
+        get(&self.hidden, uid)
+    }
+
+    /// Hide a recipe, returning whether it was shown before
+    pub(crate) fn hide(&self, uid: Uid, slug: &str, title: &str) -> Result<bool, Error> {
+        update(&self.hidden, uid, |hidden: &mut Vec<SavedRecipe>| {
+            if hidden.iter().any(|recipe| recipe.slug == slug) {
+                return false;
+            }
+            hidden.push(SavedRecipe {
+                slug: slug.to_string(),
+                title: title.to_string(),
+            });
+            true
+        })
+    }
+
+    /// Show a hidden recipe again
+    pub(crate) fn unhide(&self, uid: Uid, slug: &str) -> Result<Option<SavedRecipe>, Error> {
+        update(&self.hidden, uid, |hidden: &mut Vec<SavedRecipe>| {
+            let position = hidden.iter().position(|recipe| recipe.slug == slug)?;
+            Some(hidden.remove(position))
+        })
+    }
+
+    /// Recipes cooked from a shelf, newest first
+    pub(crate) fn cooked(&self, uid: Uid) -> Result<Vec<Cooked>, Error> {
+        get(&self.cooked, uid)
+    }
+
+    pub(crate) fn add_cooked(&self, uid: Uid, cooked: Cooked) -> Result<(), Error> {
+        update(&self.cooked, uid, |log: &mut Vec<Cooked>| {
+            log.insert(0, cooked.clone());
+            log.truncate(MAX_COOKED);
+        })
+    }
+
+    /// Recipes cooked recently with how much to demote each, see `cooked_weight`
+    pub(crate) fn recently_cooked(&self, uid: Uid, now: u64) -> Result<Vec<(String, f32)>, Error> {
+        let mut weights: Vec<(String, f32)> = vec![];
+        for cooked in self.cooked(uid)? {
+            let weight = cooked_weight(cooked.at, now);
+            if weight > 0.0 && !weights.iter().any(|(slug, _)| *slug == cooked.slug) {
+                weights.push((cooked.slug, weight));
+            }
+        }
+        Ok(weights)
+    }
+}
+
+fn get<T: DeserializeOwned>(tree: &sled::Tree, uid: Uid) -> Result<Vec<T>, Error> {
+    match tree.get(uid.to_be_bytes())? {
+        Some(items) => Ok(serde_json::from_slice(&items)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Change a shelf's list, retrying if it was changed by another request in the meantime
+fn update<T: Serialize + DeserializeOwned, R>(
+    tree: &sled::Tree,
+    uid: Uid,
+    mut change: impl FnMut(&mut Vec<T>) -> R,
+) -> Result<R, Error> {
+    let key = uid.to_be_bytes();
+    loop {
+        let current = tree.get(key)?;
+        let mut items: Vec<T> = match &current {
+            Some(items) => serde_json::from_slice(items)?,
+            None => vec![],
+        };
+        let result = change(&mut items);
+        let new = if items.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_vec(&items)?)
+        };
+        if tree.compare_and_swap(key, current, new)?.is_ok() {
+            return Ok(result);
+        }
+    }
+}
+
+impl FromRequest for RecipeLists {
+    type Error = Error;
+    type Future = Ready<Result<RecipeLists, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
+        ready(RecipeLists::new(sled.get_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn recipe_lists() -> RecipeLists {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        RecipeLists::new(&sled).unwrap()
+    }
+
+    fn cooked(slug: &str, at: u64) -> Cooked {
+        Cooked {
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            at,
+        }
+    }
+
+    #[test]
+    fn toggle_favourites() {
+        let recipe_lists = recipe_lists();
+
+        assert!(recipe_lists
+            .toggle_favourite(1, "pancakes", "Pancakes")
+            .unwrap());
+        assert!(recipe_lists
+            .toggle_favourite(1, "omelette", "Omelette")
+            .unwrap());
+        assert!(!recipe_lists
+            .toggle_favourite(1, "pancakes", "Pancakes")
+            .unwrap());
+
+        let slugs: Vec<_> = recipe_lists
+            .favourites(1)
+            .unwrap()
+            .into_iter()
+            .map(|favourite| favourite.slug)
+            .collect();
+        assert_eq!(slugs, vec!["omelette"]);
+        assert!(recipe_lists.favourites(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn hide_and_unhide_recipes() {
+        let recipe_lists = recipe_lists();
+
+        assert!(recipe_lists.hide(1, "pancakes", "Pancakes").unwrap());
+        assert!(!recipe_lists.hide(1, "pancakes", "Pancakes").unwrap());
+        assert_eq!(recipe_lists.hidden(1).unwrap().len(), 1);
+
+        let shown = recipe_lists.unhide(1, "pancakes").unwrap().unwrap();
+        assert_eq!(shown.title, "Pancakes");
+        assert!(recipe_lists.unhide(1, "pancakes").unwrap().is_none());
+        assert!(recipe_lists.hidden(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recently_cooked_recipes_are_weighted_by_age() {
+        let recipe_lists = recipe_lists();
+        let now = 100 * DAY;
+        recipe_lists
+            .add_cooked(1, cooked("soup", now - 20 * DAY))
+            .unwrap();
+        recipe_lists
+            .add_cooked(1, cooked("curry", now - 7 * DAY))
+            .unwrap();
+        recipe_lists
+            .add_cooked(1, cooked("pancakes", now - 3 * DAY))
+            .unwrap();
+        recipe_lists.add_cooked(1, cooked("curry", now)).unwrap();
+
+        assert_eq!(recipe_lists.cooked(1).unwrap()[0], cooked("curry", now));
+        assert_eq!(
+            recipe_lists.recently_cooked(1, now).unwrap(),
+            vec![
+                ("curry".to_string(), 2.0),
+                ("pancakes".to_string(), cooked_weight(now - 3 * DAY, now)),
+            ]
+        );
+        assert_eq!(cooked_weight(now - 7 * DAY, now), 1.0);
+    }
+}
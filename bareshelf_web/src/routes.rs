@@ -1,18 +1,34 @@
-use std::cmp;
+use std::{cmp, collections::HashMap, str::FromStr};
 
-use actix_session::Session;
-use actix_web::{error, web, Error, HttpRequest, HttpResponse, Responder};
-use bareshelf::{IngredientQuery, RecipeQuery};
+use actix_multipart::Multipart;
+use actix_session::{Session, SessionExt};
+use actix_web::{error, http::header, web, Either, Error, HttpRequest, HttpResponse, Responder};
+use bareshelf::{
+    Allergen, IngredientQuery, IngredientResolver, IngredientSlug, KeyIngredientMode, Popularity,
+    RecipeQuery, Resolution,
+};
+use futures::TryStreamExt;
 use rand::seq::SliceRandom;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
-    flash::{FlashMessage, FlashResponse},
-    sharing::{decode_share_token, encode_share_token},
+    accounts::{Accounts, MIN_PASSWORD_LEN},
+    bulk_import,
+    diets::{Diet, DietBans},
+    flash::{FlashMessage, FlashResponse, UndoHide},
+    households::Households,
+    recipe_lists::{Cooked, RecipeLists},
+    sharing::{self, encode_share_token, Permission, ShareToken, ShareTokens},
     shelf,
     shelf::{ingredient_slugs, Shelf},
-    views::RecipeSearchResult,
+    shelf_file::{self, ShelfFile},
+    shopping_list::{ShoppingItem, ShoppingLists},
+    views::{
+        self, CookedRecipe, ExpiringIngredient, HistoryItem, HouseholdMember, ImportLine,
+        IngredientBucket, Pairing, RecipeSearchResult, RefineChoice, SettingChoice, SharedLink,
+        ShelfChange, ShelfIngredient, ShoppingListItem,
+    },
 };
 
 /// Basic route with no dependencies to check the server is up
@@ -29,13 +45,62 @@ pub(crate) async fn index(
     tera: web::Data<tera::Tera>,
     searcher: web::Data<bareshelf::Searcher>,
     shelf: Shelf,
+    recipe_lists: RecipeLists,
     flash: FlashMessage,
+    refine: web::Query<Refine>,
 ) -> Result<HttpResponse, Error> {
     let mut ctx = tera::Context::new();
 
-    let ingredients = ingredient_slugs(&shelf.get_ingredients(&shelf::Bucket::Ingredients)?);
-
+    ctx.insert("undo_hide", &flash.undo_hide());
     ctx.insert("flash", &flash.take());
+    insert_shelf_recipes(&mut ctx, &searcher, &shelf, &recipe_lists, Some(&refine))?;
+
+    render(tera, "index.html", Some(&ctx))
+}
+
+/// How many sites, chefs and ingredients to offer for narrowing the recipe listing
+const REFINE_CHOICES: usize = 10;
+
+/// Narrow the recipe listing to a single site, chef or ingredient
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Refine {
+    host: Option<String>,
+    chef: Option<String>,
+    ingredient: Option<String>,
+}
+
+impl Refine {
+    fn apply(&self, query: RecipeQuery) -> RecipeQuery {
+        query
+            .hosts(self.host.as_slice())
+            .chefs(self.chef.as_slice())
+            .required_ingredients(self.ingredient.as_slice())
+    }
+}
+
+/// Add the recipes that can be made from a shelf to a template context
+///
+/// When refining, the listing is narrowed accordingly and the choices for narrowing it further
+/// are added too.
+fn insert_shelf_recipes(
+    ctx: &mut tera::Context,
+    searcher: &bareshelf::Searcher,
+    shelf: &Shelf,
+    recipe_lists: &RecipeLists,
+    refine: Option<&Refine>,
+) -> Result<(), Error> {
+    let shelf_ingredients = shelf.get_ingredients(&shelf::Bucket::Ingredients)?;
+    let ingredients = ingredient_slugs(&shelf_ingredients);
+
+    let use_up = use_up_weights(shelf)?;
+    let use_up_names: Vec<&str> = shelf_ingredients
+        .iter()
+        .filter(|ingredient| use_up.iter().any(|(slug, _)| *slug == ingredient.slug))
+        .map(|ingredient| ingredient.name.as_str())
+        .collect();
+    if !use_up_names.is_empty() {
+        ctx.insert("use_up", &use_up_names);
+    }
 
     if ingredients.is_empty() {
         let empty: Vec<String> = vec![];
@@ -43,39 +108,40 @@ pub(crate) async fn index(
         ctx.insert("one_missing", &empty);
         ctx.insert("more_missing", &empty);
     } else {
-        let query = RecipeQuery::default()
-            .shelf_ingredients(&ingredients)
-            .key_ingredients(&ingredient_slugs(
-                &shelf.get_ingredients(&shelf::Bucket::KeyIngredients)?,
-            ))
-            .banned_ingredients(&ingredient_slugs(
-                &shelf.get_ingredients(&shelf::Bucket::BannedIngredients)?,
-            ));
-
+        let diet_bans = diet_bans(searcher)?;
+        let mut query = shelf_recipe_query(shelf, &use_up, recipe_lists, &diet_bans)?;
+        if let Some(refine) = refine {
+            query = refine.apply(query);
+        }
         let recipes = searcher
             .recipes(query)
             .map_err(|_| error::ErrorInternalServerError("failed to search"))?;
-
-        let mut can_make_now = recipes
-            .can_make_now()
-            .map(RecipeSearchResult::from)
-            .collect::<Vec<_>>();
-
-        let mut one_missing = recipes
-            .one_missing()
-            .map(RecipeSearchResult::from)
-            .collect::<Vec<_>>();
-
-        let mut more_missing = recipes
-            .more_missing()
-            .map(RecipeSearchResult::from)
-            .collect::<Vec<_>>();
-
+        let recently_cooked = recipe_lists.recently_cooked(shelf.uid(), sharing::now())?;
+        let favourites = recipe_lists.favourites(shelf.uid())?;
         let mut rng = rand::thread_rng();
 
-        can_make_now.shuffle(&mut rng);
-        one_missing.shuffle(&mut rng);
-        more_missing.shuffle(&mut rng);
+        // Shuffle each section for variety, but keep recipes cooked recently at the end
+        let mut section = |recipes: Vec<&bareshelf::RecipeSearchResult>| {
+            let mut recipes = recipes;
+            recipes.shuffle(&mut rng);
+            recipes.sort_by_key(|recipe| {
+                recently_cooked
+                    .iter()
+                    .any(|(slug, _)| *slug == recipe.recipe.slug)
+            });
+            recipes
+                .into_iter()
+                .map(|recipe| {
+                    RecipeSearchResult::from(recipe)
+                        .with_favourites(&favourites)
+                        .with_diets(&diet_bans)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let can_make_now = section(recipes.can_make_now().collect());
+        let one_missing = section(recipes.one_missing().collect());
+        let more_missing = section(recipes.more_missing().collect());
 
         ctx.insert("can_make_now", &can_make_now);
         ctx.insert("one_missing", &one_missing);
@@ -95,35 +161,220 @@ pub(crate) async fn index(
                 &next_ingredients[..cmp::min(20, next_ingredients.len())],
             );
         }
+
+        if let Some(refine) = refine {
+            insert_refine_choices(ctx, searcher, refine, recipes.facet_counts())?;
+        }
     }
 
-    render(tera, "index.html", Some(&ctx))
+    Ok(())
+}
+
+/// Add the sites, chefs and ingredients the recipe listing can be narrowed to
+fn insert_refine_choices(
+    ctx: &mut tera::Context,
+    searcher: &bareshelf::Searcher,
+    refine: &Refine,
+    counts: &bareshelf::RecipeFacetCounts,
+) -> Result<(), Error> {
+    let choices = |counts: &[(String, u64)]| {
+        counts
+            .iter()
+            .take(REFINE_CHOICES)
+            .map(|(value, count)| RefineChoice::new(value.clone(), value.clone(), *count))
+            .collect::<Vec<_>>()
+    };
+
+    let slugs: Vec<IngredientSlug> = counts
+        .ingredients
+        .iter()
+        .take(REFINE_CHOICES)
+        .map(|(slug, _)| slug.clone())
+        .collect();
+    let names: HashMap<String, String> = if slugs.is_empty() {
+        HashMap::new()
+    } else {
+        searcher
+            .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(slugs.len()))
+            .map_err(|_| error::ErrorInternalServerError("failed to search ingredients"))?
+            .into_iter()
+            .map(|ingredient| (ingredient.slug, ingredient.name))
+            .collect()
+    };
+    let ingredients: Vec<RefineChoice> = counts
+        .ingredients
+        .iter()
+        .take(REFINE_CHOICES)
+        .map(|(slug, count)| {
+            let slug = String::from(slug);
+            let name = names.get(&slug).cloned().unwrap_or_else(|| slug.clone());
+            RefineChoice::new(slug, name, *count)
+        })
+        .collect();
+
+    ctx.insert("refine", refine);
+    if let Some(slug) = &refine.ingredient {
+        ctx.insert("refine_ingredient", names.get(slug).unwrap_or(slug));
+    }
+    ctx.insert("refine_hosts", &choices(&counts.hosts));
+    ctx.insert("refine_chefs", &choices(&counts.chefs));
+    ctx.insert("refine_ingredients", &ingredients);
+    Ok(())
+}
+
+/// How urgently each shelf ingredient close to its best before date should be used up
+fn use_up_weights(shelf: &Shelf) -> Result<Vec<(String, f32)>, Error> {
+    let today = chrono::Local::now().date_naive();
+    Ok(shelf
+        .best_before()?
+        .iter()
+        .map(|(slug, date)| (slug.clone(), shelf::use_up_weight(*date, today)))
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect())
+}
+
+/// The ingredients each diet rules out, from the ingredient tags in the index
+fn diet_bans(searcher: &bareshelf::Searcher) -> Result<DietBans, Error> {
+    DietBans::new(searcher)
+        .map_err(|_| error::ErrorInternalServerError("failed to search ingredients"))
+}
+
+/// Query for the recipes that can be made from a shelf
+///
+/// Hidden recipes are left out, as are recipes breaking the shelf's diets or containing its
+/// allergens, and recipes cooked recently rank lower.
+fn shelf_recipe_query(
+    shelf: &Shelf,
+    use_up: &[(String, f32)],
+    recipe_lists: &RecipeLists,
+    diet_bans: &DietBans,
+) -> Result<RecipeQuery, Error> {
+    let settings = shelf.settings()?;
+    let mut banned = ingredient_slugs(&shelf.get_ingredients(&shelf::Bucket::BannedIngredients)?);
+    banned.extend(diet_bans.banned(&settings.diets));
+    Ok(RecipeQuery::default()
+        .shelf_ingredients(&ingredient_slugs(
+            &shelf.get_ingredients(&shelf::Bucket::Ingredients)?,
+        ))
+        .key_ingredients(&ingredient_slugs(
+            &shelf.get_ingredients(&shelf::Bucket::KeyIngredients)?,
+        ))
+        .key_ingredient_mode(settings.key_ingredient_mode)
+        .banned_ingredients(&banned)
+        .prioritise_ingredients(use_up)
+        .excluded_recipes(
+            &recipe_lists
+                .hidden(shelf.uid())?
+                .into_iter()
+                .map(|recipe| recipe.slug)
+                .collect::<Vec<_>>(),
+        )
+        .demote_recipes(&recipe_lists.recently_cooked(shelf.uid(), sharing::now())?)
+        .excluded_allergens(&settings.allergens))
 }
 
 pub(crate) async fn ingredients(
     tera: web::Data<tera::Tera>,
     searcher: web::Data<bareshelf::Searcher>,
     shelf: Shelf,
+    households: Households,
     flash: FlashMessage,
 ) -> Result<HttpResponse, Error> {
     let mut ctx = tera::Context::new();
 
     let ingredients = shelf.get_ingredients(&shelf::Bucket::Ingredients)?;
-    ctx.insert("ingredients", &ingredients);
     let key_ingredients = shelf.get_ingredients(&shelf::Bucket::KeyIngredients)?;
-    ctx.insert("key_ingredients", &key_ingredients);
     let banned_ingredients = shelf.get_ingredients(&shelf::Bucket::BannedIngredients)?;
-    ctx.insert("banned_ingredients", &banned_ingredients);
+    let settings = shelf.settings()?;
+    // Don't suggest ingredients the shelf's diets rule out
+    let mut banned = ingredient_slugs(&banned_ingredients);
+    banned.extend(diet_bans(&searcher)?.banned(&settings.diets));
     let popular_ingredients = searcher
         .popular_ingredients(
             RecipeQuery::default()
                 .shelf_ingredients(&ingredient_slugs(&ingredients))
                 .key_ingredients(&ingredient_slugs(&key_ingredients))
-                .banned_ingredients(&ingredient_slugs(&banned_ingredients))
+                .key_ingredient_mode(settings.key_ingredient_mode)
+                .banned_ingredients(&banned)
+                .excluded_allergens(&settings.allergens)
+                .popularity(Popularity::CloseToShelf)
                 .limit(50),
         )
         .map_err(|_| error::ErrorInternalServerError("failed to execute query"))?;
     ctx.insert("popular_ingredients", &popular_ingredients);
+    ctx.insert(
+        "diets",
+        &Diet::ALL
+            .iter()
+            .map(|diet| SettingChoice::new(diet.slug(), diet.name(), settings.diets.contains(diet)))
+            .collect::<Vec<_>>(),
+    );
+    ctx.insert(
+        "allergens",
+        &Allergen::ALL
+            .iter()
+            .map(|allergen| {
+                SettingChoice::new(
+                    allergen.slug(),
+                    allergen.name(),
+                    settings.allergens.contains(allergen),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    let (key_ingredient_mode, key_ingredients_at_least) = match settings.key_ingredient_mode {
+        KeyIngredientMode::Any => ("any", 2),
+        KeyIngredientMode::All => ("all", 2),
+        KeyIngredientMode::AtLeast(n) => ("at-least", n),
+    };
+    ctx.insert("key_ingredient_mode", key_ingredient_mode);
+    ctx.insert("key_ingredients_at_least", &key_ingredients_at_least);
+
+    // On a shared shelf show who last changed each ingredient
+    let household = households
+        .get(shelf.uid())?
+        .filter(|household| household.is_shared());
+    let changes = match household {
+        Some(_) => shelf.changes()?,
+        None => vec![],
+    };
+    let with_changes = |bucket: shelf::Bucket, ingredients: Vec<bareshelf::Ingredient>| {
+        ingredients
+            .into_iter()
+            .map(|ingredient| {
+                let changed_by = household.as_ref().and_then(|household| {
+                    changes
+                        .iter()
+                        .find(|change| change.bucket == bucket && change.ingredient == ingredient)
+                        .map(|change| household.name_of(change.member).to_string())
+                });
+                ShelfIngredient::new(ingredient, changed_by)
+            })
+            .collect::<Vec<_>>()
+    };
+    ctx.insert(
+        "ingredients",
+        &with_changes(shelf::Bucket::Ingredients, ingredients),
+    );
+    ctx.insert(
+        "key_ingredients",
+        &with_changes(shelf::Bucket::KeyIngredients, key_ingredients),
+    );
+    ctx.insert(
+        "banned_ingredients",
+        &with_changes(shelf::Bucket::BannedIngredients, banned_ingredients),
+    );
+    if let Some(household) = &household {
+        ctx.insert("household_size", &household.members.len());
+        ctx.insert(
+            "recent_changes",
+            &changes
+                .iter()
+                .take(10)
+                .map(|change| ShelfChange::new(change, household))
+                .collect::<Vec<_>>(),
+        );
+    }
 
     ctx.insert("flash", &flash.take());
 
@@ -132,62 +383,231 @@ pub(crate) async fn ingredients(
 
 #[derive(Deserialize)]
 pub struct IngredientForm {
-    ingredient: String,
+    /// Free-form text typed by the user
+    ingredient: Option<String>,
+    /// Slug of an ingredient that was shown to the user
+    slug: Option<String>,
     bucket: shelf::Bucket,
     redirect: Option<String>,
 }
 
 pub(crate) async fn add_ingredient(
+    tera: web::Data<tera::Tera>,
     form: web::Form<IngredientForm>,
     searcher: web::Data<bareshelf::Searcher>,
     shelf: Shelf,
-) -> Result<FlashResponse, Error> {
-    let mut ingredients = searcher
-        .ingredients(IngredientQuery::by_name(&form.ingredient))
-        .map_err(|_| error::ErrorInternalServerError("search error"))?;
+) -> Result<Either<FlashResponse, HttpResponse>, Error> {
+    let redirect = form.redirect.clone().unwrap_or_else(|| "/".to_string());
+    let input = form.ingredient.as_deref().unwrap_or_default();
 
-    let ingredient = if ingredients.is_empty() {
-        let mut ingredients =
-            get_ingredients_by_prefix(&shelf, searcher.as_ref(), &form.bucket, &form.ingredient)?;
-
-        if ingredients.is_empty() {
-            None
-        } else {
-            Some(ingredients.remove(0))
+    let resolution = if let Some(slug) = &form.slug {
+        match get_ingredient_by_slug(searcher.as_ref(), slug)? {
+            Some(ingredient) => Resolution::Exact(ingredient),
+            None => {
+                return Ok(Either::Left(FlashResponse::new(
+                    Some(format!("Unknown ingredient \"{}\"", slug)),
+                    &redirect,
+                )))
+            }
         }
     } else {
-        Some(ingredients.remove(0))
+        let existing_ingredients = shelf.get_ingredients(&form.bucket)?;
+
+        IngredientResolver::new(searcher.as_ref())
+            .excluding(&existing_ingredients)
+            .resolve(input)
+            .map_err(|_| error::ErrorInternalServerError("search error"))?
     };
 
-    let flash = if let Some(ingredient) = ingredient {
-        if shelf.add_ingredient(&form.bucket, &ingredient)? {
-            format!(
-                "Added {} to your {}",
-                ingredient.name,
-                form.bucket.flash_name()
-            )
-        } else {
-            format!(
-                "{} is already in your {}",
-                ingredient.name,
-                form.bucket.flash_name()
-            )
+    let flash = match resolution {
+        Resolution::Exact(ingredient) => {
+            if shelf.add_ingredient(&form.bucket, &ingredient)? {
+                format!(
+                    "Added {} to your {}",
+                    ingredient.name,
+                    form.bucket.flash_name()
+                )
+            } else {
+                format!(
+                    "{} is already in your {}",
+                    ingredient.name,
+                    form.bucket.flash_name()
+                )
+            }
+        }
+        Resolution::Candidates { candidates, .. } => {
+            let mut ctx = tera::Context::new();
+            ctx.insert("input", input);
+            ctx.insert("candidates", &candidates);
+            ctx.insert("bucket", &form.bucket);
+            ctx.insert("bucket_name", &form.bucket.flash_name());
+            ctx.insert("redirect", &redirect);
+
+            return render(tera, "choose-ingredient.html", Some(&ctx)).map(Either::Right);
         }
+        Resolution::NotFound => {
+            format!("No ingredients found matching \"{}\"", input)
+        }
+    };
+
+    Ok(Either::Left(FlashResponse::new(Some(flash), &redirect)))
+}
+
+/// Everything about one ingredient, with buttons to add it to or remove it from each bucket
+///
+/// The recipes listed are those only missing this ingredient, the ones counted for it in
+/// "ingredients that will open you up to new recipes".
+pub(crate) async fn ingredient(
+    tera: web::Data<tera::Tera>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+    flash: FlashMessage,
+    slug: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let ingredient = get_ingredient_by_slug(&searcher, &slug)?
+        .ok_or_else(|| error::ErrorNotFound("unknown ingredient"))?;
+    let recipe_count = searcher
+        .ingredient_recipe_count(&ingredient.slug)
+        .map_err(|_| error::ErrorInternalServerError("failed to search"))?;
+    let shelf_slugs = ingredient_slugs(&shelf.get_ingredients(&shelf::Bucket::Ingredients)?);
+    let pairings: Vec<Pairing> = searcher
+        .ingredient_pairings(&ingredient.slug, 10)
+        .map_err(|_| error::ErrorInternalServerError("failed to search"))?
+        .iter()
+        .map(|pairing| Pairing::new(pairing, shelf_slugs.contains(&pairing.ingredient.slug)))
+        .collect();
+
+    let mut buckets = vec![];
+    for bucket in shelf::Bucket::ALL {
+        let contains = shelf
+            .get_ingredients(&bucket)?
+            .iter()
+            .any(|shelf_ingredient| shelf_ingredient.slug == ingredient.slug);
+        buckets.push(IngredientBucket::new(bucket, contains));
+    }
+
+    let on_shelf = shelf_slugs.contains(&ingredient.slug);
+    let recipes: Vec<RecipeSearchResult> = if on_shelf || shelf_slugs.is_empty() {
+        vec![]
     } else {
-        format!("No ingredients found matching \"{}\"", form.ingredient)
+        let diet_bans = diet_bans(&searcher)?;
+        let query =
+            shelf_recipe_query(&shelf, &use_up_weights(&shelf)?, &recipe_lists, &diet_bans)?
+                .required_ingredients(std::slice::from_ref(&ingredient.slug));
+        let favourites = recipe_lists.favourites(shelf.uid())?;
+        searcher
+            .recipes(query)
+            .map_err(|_| error::ErrorInternalServerError("failed to search"))?
+            .one_missing()
+            .map(|recipe| {
+                RecipeSearchResult::from(recipe)
+                    .with_favourites(&favourites)
+                    .with_diets(&diet_bans)
+            })
+            .collect()
     };
 
-    Ok(FlashResponse::new(
-        Some(flash),
-        form.redirect.as_ref().unwrap_or(&"/".to_string()),
-    ))
+    let mut ctx = tera::Context::new();
+    ctx.insert("undo_hide", &flash.undo_hide());
+    ctx.insert("flash", &flash.take());
+    ctx.insert("redirect", &format!("/ingredients/{}", ingredient.slug));
+    ctx.insert("ingredient", &ingredient);
+    ctx.insert("recipe_count", &recipe_count);
+    ctx.insert("pairings", &pairings);
+    ctx.insert("buckets", &buckets);
+    ctx.insert("on_shelf", &on_shelf);
+    ctx.insert("recipes", &recipes);
+
+    render(tera, "ingredient.html", Some(&ctx))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct KeyIngredientModeForm {
+    /// One of "any", "all" or "at-least"
+    mode: String,
+    /// Number of key ingredients for "at-least", may be left empty otherwise
+    #[serde(default)]
+    at_least: String,
+}
+
+/// Choose how many of the key ingredients the shelf's recipes need
+pub(crate) async fn set_key_ingredient_mode(
+    form: web::Form<KeyIngredientModeForm>,
+    shelf: Shelf,
+) -> Result<FlashResponse, Error> {
+    let mode = match form.mode.as_str() {
+        "any" => KeyIngredientMode::Any,
+        "all" => KeyIngredientMode::All,
+        "at-least" => KeyIngredientMode::AtLeast(
+            form.at_least
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| error::ErrorBadRequest("invalid number of key ingredients"))?,
+        ),
+        _ => return Err(error::ErrorBadRequest("unknown key ingredient mode")),
+    };
+
+    let flash = match mode {
+        KeyIngredientMode::Any => "Recipes need one of your key ingredients".to_string(),
+        KeyIngredientMode::All => "Recipes need all of your key ingredients".to_string(),
+        KeyIngredientMode::AtLeast(n) => {
+            format!("Recipes need at least {} of your key ingredients", n)
+        }
+    };
+    let mut settings = shelf.settings()?;
+    settings.key_ingredient_mode = mode;
+    shelf.set_settings(&settings)?;
+
+    Ok(FlashResponse::new(Some(flash), "/ingredients"))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PairingsQuery {
+    ingredient: String,
+}
+
+/// Ingredients that go well with an ingredient
+pub(crate) async fn pairings(
+    tera: web::Data<tera::Tera>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    query: web::Query<PairingsQuery>,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let ingredient = get_ingredient_by_slug(&searcher, &query.ingredient)?
+        .ok_or_else(|| error::ErrorNotFound("unknown ingredient"))?;
+    let shelf_slugs = ingredient_slugs(&shelf.get_ingredients(&shelf::Bucket::Ingredients)?);
+    let pairings: Vec<Pairing> = searcher
+        .ingredient_pairings(&ingredient.slug, 20)
+        .map_err(|_| error::ErrorInternalServerError("failed to search"))?
+        .iter()
+        .map(|pairing| Pairing::new(pairing, shelf_slugs.contains(&pairing.ingredient.slug)))
+        .collect();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+    ctx.insert(
+        "redirect",
+        &format!("/pairings?ingredient={}", ingredient.slug),
+    );
+    ctx.insert("ingredient", &ingredient);
+    ctx.insert("pairings", &pairings);
+
+    render(tera, "pairings.html", Some(&ctx))
 }
 
 pub(crate) async fn remove_ingredient(
     form: web::Form<IngredientForm>,
     shelf: Shelf,
 ) -> Result<FlashResponse, Error> {
-    let flash = if let Some(ingredient) = shelf.remove_ingredient(&form.bucket, &form.ingredient)? {
+    let slug = form
+        .slug
+        .as_ref()
+        .ok_or_else(|| error::ErrorBadRequest("slug is required"))?;
+    let flash = if let Some(ingredient) = shelf.remove_ingredient(&form.bucket, slug)? {
         Some(format!("Removed {} from your shelf", ingredient.name))
     } else {
         None
@@ -198,6 +618,235 @@ pub(crate) async fn remove_ingredient(
     ))
 }
 
+/// Every distinct value of a form field that may be repeated, which `web::Form` cannot collect
+fn repeated_field<T: FromStr + PartialEq>(
+    body: &[u8],
+    name: &str,
+    invalid: &'static str,
+) -> Result<Vec<T>, Error> {
+    let mut values = vec![];
+    for (key, value) in url::form_urlencoded::parse(body) {
+        if key == name {
+            let value: T = value.parse().map_err(|_| error::ErrorBadRequest(invalid))?;
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Choose the diets the shelf's recipes must keep to
+///
+/// The form has a `diet` field per chosen diet.
+pub(crate) async fn set_diets(body: web::Bytes, shelf: Shelf) -> Result<FlashResponse, Error> {
+    let diets: Vec<Diet> = repeated_field(&body, "diet", "unknown diet")?;
+
+    let flash = match diets.as_slice() {
+        [] => "Recipes no longer need to keep to a diet".to_string(),
+        diets => format!(
+            "Recipes will be {}",
+            diets
+                .iter()
+                .map(|diet| diet.name())
+                .collect::<Vec<_>>()
+                .join(" and ")
+        ),
+    };
+    let mut settings = shelf.settings()?;
+    settings.diets = diets;
+    shelf.set_settings(&settings)?;
+
+    Ok(FlashResponse::new(Some(flash), "/ingredients"))
+}
+
+/// Choose the allergens the shelf's recipes must not contain
+///
+/// The form has an `allergen` field per chosen allergen.
+pub(crate) async fn set_allergens(body: web::Bytes, shelf: Shelf) -> Result<FlashResponse, Error> {
+    let allergens: Vec<Allergen> = repeated_field(&body, "allergen", "unknown allergen")?;
+
+    let flash = match allergens.as_slice() {
+        [] => "Recipes may contain any allergen".to_string(),
+        allergens => format!(
+            "Recipes will not contain {}",
+            allergens
+                .iter()
+                .map(|allergen| allergen.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut settings = shelf.settings()?;
+    settings.allergens = allergens;
+    shelf.set_settings(&settings)?;
+
+    Ok(FlashResponse::new(Some(flash), "/ingredients"))
+}
+
+pub(crate) async fn import_shelf(
+    tera: web::Data<tera::Tera>,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+
+    render(tera, "import-shelf.html", Some(&ctx))
+}
+
+#[derive(Deserialize)]
+pub struct ImportForm {
+    list: String,
+    bucket: shelf::Bucket,
+}
+
+/// Resolve each item of a pasted list so the user can review them before importing
+pub(crate) async fn review_import_shelf(
+    tera: web::Data<tera::Tera>,
+    form: web::Form<ImportForm>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+) -> Result<HttpResponse, Error> {
+    let existing_ingredients = shelf.get_ingredients(&form.bucket)?;
+    let resolver = IngredientResolver::new(searcher.as_ref())
+        .excluding(&existing_ingredients)
+        .with_limit(5);
+
+    let lines = bulk_import::parse_list(&form.list)
+        .into_iter()
+        .map(|line| {
+            let resolution = resolver
+                .resolve(&line)
+                .map_err(|_| error::ErrorInternalServerError("search error"))?;
+            Ok(match resolution {
+                Resolution::Exact(ingredient) => ImportLine::Matched {
+                    line,
+                    existing: existing_ingredients.contains(&ingredient),
+                    ingredient,
+                },
+                Resolution::Candidates { candidates, .. } => {
+                    ImportLine::Ambiguous { line, candidates }
+                }
+                Resolution::NotFound => ImportLine::Unmatched { line },
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("lines", &lines);
+    ctx.insert("bucket", &form.bucket);
+    ctx.insert("bucket_name", &form.bucket.flash_name());
+
+    render(tera, "import-shelf-review.html", Some(&ctx))
+}
+
+/// Add the ingredients confirmed on the review page
+///
+/// The form has a `slug` field per confirmed item, which `web::Form` cannot collect.
+pub(crate) async fn confirm_import_shelf(
+    body: web::Bytes,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+) -> Result<FlashResponse, Error> {
+    let mut bucket = None;
+    let mut slugs = vec![];
+    for (key, value) in url::form_urlencoded::parse(&body) {
+        match key.as_ref() {
+            "bucket" => bucket = Some(value.into_owned()),
+            "slug" if !value.is_empty() => slugs.push(IngredientSlug::from(value.as_ref())),
+            _ => {}
+        }
+    }
+    let bucket: shelf::Bucket = bucket
+        .and_then(|bucket| bucket.parse().ok())
+        .ok_or_else(|| error::ErrorBadRequest("invalid bucket"))?;
+
+    let ingredients = if slugs.is_empty() {
+        vec![]
+    } else {
+        searcher
+            .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(slugs.len()))
+            .map_err(|_| error::ErrorInternalServerError("failed to search ingredients"))?
+    };
+    let added = shelf.add_ingredients(&bucket, &ingredients)?;
+
+    Ok(FlashResponse::new(
+        Some(format!(
+            "Added {} ingredients to your {}",
+            added,
+            bucket.flash_name()
+        )),
+        "/ingredients",
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Export {
+    format: Option<shelf_file::Format>,
+}
+
+/// Download the whole shelf as a file
+pub(crate) async fn export_shelf(
+    export: web::Query<Export>,
+    shelf: Shelf,
+) -> Result<HttpResponse, Error> {
+    let format = export.format.unwrap_or(shelf_file::Format::Json);
+    let body = ShelfFile::from_shelf(&shelf)?.to_bytes(format)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .append_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"shelf.{}\"", format.extension()),
+        ))
+        .body(body))
+}
+
+/// Largest shelf file that can be uploaded
+const MAX_SHELF_FILE_SIZE: usize = 1024 * 1024;
+
+/// Upload a shelf file previously downloaded with `export_shelf`
+pub(crate) async fn upload_shelf(
+    mut payload: Multipart,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+) -> Result<FlashResponse, Error> {
+    let mut data = vec![];
+    while let Some(mut field) = payload.try_next().await? {
+        if field.name() != "file" {
+            continue;
+        }
+        while let Some(chunk) = field.try_next().await? {
+            if data.len() + chunk.len() > MAX_SHELF_FILE_SIZE {
+                return Err(error::ErrorPayloadTooLarge("shelf file is too large"));
+            }
+            data.extend_from_slice(&chunk);
+        }
+    }
+
+    let flash = match ShelfFile::parse(&data) {
+        Ok(file) => {
+            let report = file.import(&shelf, searcher.as_ref())?;
+            let mut flash = format!("Imported {} ingredients", report.added);
+            if !report.missing.is_empty() {
+                flash.push_str(&format!(
+                    ". These ingredients no longer exist: {}",
+                    report
+                        .missing
+                        .iter()
+                        .map(|ingredient| ingredient.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            flash
+        }
+        Err(err) => format!("Could not read shelf file: {}", err),
+    };
+
+    Ok(FlashResponse::new(Some(flash), "/ingredients"))
+}
+
 #[derive(Deserialize)]
 pub struct Search {
     term: String,
@@ -217,53 +866,922 @@ pub(crate) async fn api_ingredients(
     )?))
 }
 
+pub(crate) async fn account(
+    tera: web::Data<tera::Tera>,
+    session: Session,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+    ctx.insert("account", &session_account(&session)?);
+    ctx.insert("min_password_len", &MIN_PASSWORD_LEN);
+
+    render(tera, "account.html", Some(&ctx))
+}
+
+#[derive(Deserialize)]
+pub struct AccountForm {
+    email: String,
+    password: String,
+}
+
+/// Create an account that owns the current shelf
+///
+/// If the shelf is a household the account takes over the session's place in it. A member of
+/// someone else's household owns the shelf they were using before they joined instead.
+pub(crate) async fn signup(
+    form: web::Form<AccountForm>,
+    accounts: Accounts,
+    households: Households,
+    shelf: Shelf,
+    session: Session,
+) -> Result<FlashResponse, Error> {
+    let uid = match households.get(shelf.uid())? {
+        Some(household) if household.owner != shelf.member() => household
+            .member(shelf.member())
+            .map(|member| member.home)
+            .unwrap_or_else(|| shelf.uid()),
+        _ => shelf.uid(),
+    };
+    let flash = match accounts.create(&form.email, &form.password, uid) {
+        Ok(account) => {
+            households.rename_member(shelf.uid(), shelf.member(), account.member())?;
+            session
+                .insert("account", &account.email)
+                .map_err(|_| error::ErrorInternalServerError("failed to update session"))?;
+            format!("Signed up as {}", account.email)
+        }
+        Err(crate::error::Error::Other(message)) => message,
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(FlashResponse::new(Some(flash), "/account"))
+}
+
+/// Log in to an account, moving anything on an anonymous shelf to the account's shelf
+///
+/// A shelf belonging to another account or to a household is left as it is, the session just
+/// switches to the account's shelf.
+pub(crate) async fn login(
+    form: web::Form<AccountForm>,
+    accounts: Accounts,
+    households: Households,
+    shelf: Shelf,
+    session: Session,
+) -> Result<FlashResponse, Error> {
+    let account = match accounts.authenticate(&form.email, &form.password) {
+        Ok(Some(account)) => account,
+        Ok(None) | Err(crate::error::Error::Other(_)) => {
+            return Ok(FlashResponse::new(
+                Some("Incorrect email or password".to_string()),
+                "/account",
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let anonymous = session_account(&session)?.is_none()
+        && !households
+            .get(shelf.uid())?
+            .is_some_and(|household| household.member(shelf.member()).is_some());
+    if anonymous && shelf.uid() != account.uid {
+        shelf.for_uid(account.uid).merge_from(shelf.uid())?;
+        shelf.remove_all()?;
+    }
+    session
+        .insert("uid", account.uid)
+        .and_then(|_| session.insert("account", &account.email))
+        .map_err(|_| error::ErrorInternalServerError("failed to update session"))?;
+
+    Ok(FlashResponse::new(
+        Some(format!("Logged in as {}", account.email)),
+        "/",
+    ))
+}
+
+/// Log out, starting a new empty anonymous shelf
+pub(crate) async fn logout(session: Session) -> Result<FlashResponse, Error> {
+    session.clear();
+
+    Ok(FlashResponse::new(
+        Some("Logged out".to_string()),
+        "/account",
+    ))
+}
+
+fn session_account(session: &Session) -> Result<Option<String>, Error> {
+    session
+        .get("account")
+        .map_err(|_| error::ErrorBadRequest("invalid session"))
+}
+
 #[derive(Deserialize)]
 pub(crate) struct Share {
     token: Option<String>,
 }
 
+/// Manage share links, or join a shelf from a share link
 pub(crate) async fn share_shelf(
     tera: web::Data<tera::Tera>,
     shelf: Shelf,
-    session: Session,
     share: web::Query<Share>,
+    (share_tokens, households): (ShareTokens, Households),
     app_data: web::Data<crate::AppData>,
+    flash: FlashMessage,
     req: HttpRequest,
-) -> Result<HttpResponse, Error> {
+) -> Result<Either<HttpResponse, FlashResponse>, Error> {
     let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
     if let Some(ref token) = share.token {
-        let uid = decode_share_token(app_data.cookie_key.encryption(), token)?;
-
-        if shelf.uid() != uid {
-            session
-                .insert("uid", uid)
-                .map_err(|_| error::ErrorInternalServerError("failed to update shelf"))?;
-            shelf
-                .remove_all()
-                .map_err(|_| error::ErrorInternalServerError("failed to clean up old shelf"))?;
-            ctx.insert("imported", &true);
-        } else {
-            ctx.insert("imported", &false);
+        let share_token = match share_tokens.validate(app_data.cookie_key.encryption(), token) {
+            Ok(share_token) => share_token,
+            Err(err) => {
+                return Ok(Either::Right(FlashResponse::new(
+                    Some(err.to_string()),
+                    "/",
+                )))
+            }
+        };
+        if share_token.permission == Permission::View {
+            return Ok(Either::Right(FlashResponse::new(
+                None,
+                &format!("/shared-shelf?token={}", token),
+            )));
         }
-    } else {
-        let connection_info = req.connection_info();
-        let token = encode_share_token(app_data.cookie_key.encryption(), shelf.uid())?;
-        ctx.insert("token", &token);
+
+        ctx.insert("token", token);
+        ctx.insert("own_shelf", &(shelf.uid() == share_token.uid));
         ctx.insert(
-            "share_url",
-            &format!(
-                "{}://{}{}?token={}",
-                connection_info.scheme(),
-                connection_info.host(),
-                req.uri().path(),
-                token
-            ),
+            "shared_ingredients",
+            &shelf
+                .for_uid(share_token.uid)
+                .get_ingredients(&shelf::Bucket::Ingredients)?,
         );
-    }
-    render(tera, "share-shelf.html", Some(&ctx))
-}
+    } else {
+        let connection_info = req.connection_info();
+        let links = share_tokens
+            .issued(shelf.uid(), sharing::now())?
+            .iter()
+            .map(|share_token| {
+                let token = encode_share_token(app_data.cookie_key.encryption(), share_token)?;
+                let path = match share_token.permission {
+                    Permission::View => "/shared-shelf",
+                    Permission::Join => "/share-shelf",
+                };
+                Ok(SharedLink::new(
+                    share_token,
+                    format!(
+                        "{}://{}{}?token={}",
+                        connection_info.scheme(),
+                        connection_info.host(),
+                        path,
+                        token
+                    ),
+                    sharing::now(),
+                ))
+            })
+            .collect::<Result<Vec<_>, crate::error::Error>>()?;
+        ctx.insert("links", &links);
 
-fn render(
+        if let Some(household) = households.get(shelf.uid())? {
+            ctx.insert("is_owner", &(household.owner == shelf.member()));
+            ctx.insert(
+                "household",
+                &HouseholdMember::all(&household, shelf.member()),
+            );
+        }
+    }
+    render(tera, "share-shelf.html", Some(&ctx)).map(Either::Left)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AcceptShareAction {
+    /// Switch to the shared shelf, leaving the current shelf as it is
+    Join,
+    /// Copy the shared shelf into the current shelf
+    Merge,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AcceptShare {
+    token: String,
+    action: AcceptShareAction,
+    /// Name to show other members of the household
+    name: Option<String>,
+}
+
+pub(crate) async fn accept_share(
+    form: web::Form<AcceptShare>,
+    shelf: Shelf,
+    share_tokens: ShareTokens,
+    households: Households,
+    app_data: web::Data<crate::AppData>,
+    req: HttpRequest,
+) -> Result<FlashResponse, Error> {
+    let share_token = match share_tokens.validate(app_data.cookie_key.encryption(), &form.token) {
+        Ok(share_token) => share_token,
+        Err(err) => return Ok(FlashResponse::new(Some(err.to_string()), "/")),
+    };
+    if share_token.uid == shelf.uid() {
+        return Ok(FlashResponse::new(
+            Some("That is already your shelf".to_string()),
+            "/",
+        ));
+    }
+
+    let flash = match form.action {
+        AcceptShareAction::Join if share_token.permission == Permission::Join => {
+            let session = req.get_session();
+            let name = match member_name(form.name.as_deref(), &session)? {
+                Some(name) => name,
+                None => match households.get(share_token.uid)? {
+                    Some(household) => format!("Member {}", household.members.len() + 1),
+                    None => "A member".to_string(),
+                },
+            };
+            match households.join(share_token.uid, shelf.member(), &name, shelf.uid()) {
+                Ok(_) => {}
+                Err(crate::error::Error::Other(message)) => {
+                    return Ok(FlashResponse::new(Some(message), "/"))
+                }
+                Err(err) => return Err(err.into()),
+            }
+            session
+                .insert("uid", share_token.uid)
+                .map_err(|_| error::ErrorInternalServerError("failed to update shelf"))?;
+            "You are now using the shared shelf".to_string()
+        }
+        AcceptShareAction::Join => {
+            return Err(error::ErrorForbidden("this share link is view only"));
+        }
+        AcceptShareAction::Merge => {
+            let report = shelf.merge_from(share_token.uid)?;
+            let mut flash = format!("Copied {} ingredients into your shelf", report.added);
+            if !report.conflicts.is_empty() {
+                flash.push_str(&format!(
+                    ". Kept your own choices for {}",
+                    report
+                        .conflicts
+                        .iter()
+                        .map(|ingredient| ingredient.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            flash
+        }
+    };
+
+    Ok(FlashResponse::new(Some(flash), "/ingredients"))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreateShare {
+    permission: Permission,
+    days: u64,
+    /// Name to show other members of the household when sharing with join permission
+    name: Option<String>,
+}
+
+/// Longest a share link can be valid for
+const MAX_SHARE_DAYS: u64 = 90;
+
+pub(crate) async fn create_share(
+    form: web::Form<CreateShare>,
+    shelf: Shelf,
+    share_tokens: ShareTokens,
+    households: Households,
+    session: Session,
+) -> Result<FlashResponse, Error> {
+    if form.permission == Permission::Join {
+        let name = member_name(form.name.as_deref(), &session)?;
+        households.get_or_create(
+            shelf.uid(),
+            shelf.member(),
+            name.as_deref().unwrap_or("Owner"),
+        )?;
+    }
+    let share_token = ShareToken::new(
+        shelf.uid(),
+        form.permission,
+        form.days.clamp(1, MAX_SHARE_DAYS),
+    );
+    share_tokens.issue(&share_token)?;
+
+    Ok(FlashResponse::new(
+        Some("Created a new share link".to_string()),
+        "/share-shelf",
+    ))
+}
+
+/// Changes to the shelf, newest first
+pub(crate) async fn history(
+    tera: web::Data<tera::Tera>,
+    shelf: Shelf,
+    households: Households,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let household = households.get(shelf.uid())?;
+    let now = sharing::now();
+    let items: Vec<_> = shelf
+        .history()?
+        .iter()
+        .map(|entry| {
+            let who = if entry.member == shelf.member() {
+                "You".to_string()
+            } else {
+                household
+                    .as_ref()
+                    .map(|household| household.name_of(entry.member))
+                    .unwrap_or("Someone")
+                    .to_string()
+            };
+            HistoryItem::new(entry, who, now)
+        })
+        .collect();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+    ctx.insert("history", &items);
+
+    render(tera, "history.html", Some(&ctx))
+}
+
+/// Shelf ingredients ordered by best before date, soonest first
+pub(crate) async fn use_it_up(
+    tera: web::Data<tera::Tera>,
+    shelf: Shelf,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let today = chrono::Local::now().date_naive();
+    let best_before = shelf.best_before()?;
+    let mut ingredients = shelf.get_ingredients(&shelf::Bucket::Ingredients)?;
+    // Dated ingredients first, then the rest alphabetically
+    ingredients.sort_by_key(|ingredient| {
+        (
+            best_before
+                .get(&ingredient.slug)
+                .copied()
+                .unwrap_or(chrono::NaiveDate::MAX),
+            ingredient.name.clone(),
+        )
+    });
+    let ingredients: Vec<_> = ingredients
+        .into_iter()
+        .map(|ingredient| {
+            let date = best_before.get(&ingredient.slug).copied();
+            ExpiringIngredient::new(ingredient, date, today)
+        })
+        .collect();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+    ctx.insert("ingredients", &ingredients);
+
+    render(tera, "use-it-up.html", Some(&ctx))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct BestBeforeForm {
+    slug: String,
+    /// A `YYYY-MM-DD` date, empty to clear it
+    best_before: String,
+}
+
+/// Set or clear the best before date of a shelf ingredient
+pub(crate) async fn set_best_before(
+    form: web::Form<BestBeforeForm>,
+    shelf: Shelf,
+) -> Result<FlashResponse, Error> {
+    let ingredients = shelf.get_ingredients(&shelf::Bucket::Ingredients)?;
+    let ingredient = match ingredients.iter().find(|i| i.slug == form.slug) {
+        Some(ingredient) => ingredient,
+        None => {
+            return Ok(FlashResponse::new(
+                Some("That ingredient is not on your shelf".to_string()),
+                "/use-it-up",
+            ))
+        }
+    };
+
+    let flash = match form.best_before.trim() {
+        "" => {
+            shelf.set_best_before(&ingredient.slug, None)?;
+            format!("Cleared best before date of {}", ingredient.name)
+        }
+        date => match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => {
+                shelf.set_best_before(&ingredient.slug, Some(date))?;
+                format!("{} is best before {}", ingredient.name, date)
+            }
+            Err(_) => format!("{} is not a valid date", date),
+        },
+    };
+
+    Ok(FlashResponse::new(Some(flash), "/use-it-up"))
+}
+
+/// Everything to buy, one line per ingredient
+pub(crate) async fn shopping_list(
+    tera: web::Data<tera::Tera>,
+    shelf: Shelf,
+    shopping_lists: ShoppingLists,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let mut items = shopping_lists.get(shelf.uid())?;
+    items.sort_by(|a, b| a.ingredient.name.cmp(&b.ingredient.name));
+    let items: Vec<_> = items.into_iter().map(ShoppingListItem::from).collect();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("flash", &flash.take());
+    ctx.insert("items", &items);
+
+    render(tera, "shopping-list.html", Some(&ctx))
+}
+
+/// Add a recipe's missing ingredients to the shopping list
+///
+/// The form has a `slug` field per ingredient, which `web::Form` cannot collect.
+pub(crate) async fn add_to_shopping_list(
+    body: web::Bytes,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    shopping_lists: ShoppingLists,
+) -> Result<FlashResponse, Error> {
+    let mut recipe = None;
+    let mut redirect = None;
+    let mut slugs = vec![];
+    for (key, value) in url::form_urlencoded::parse(&body) {
+        match key.as_ref() {
+            "recipe" if !value.is_empty() => recipe = Some(value.into_owned()),
+            "redirect" => redirect = Some(value.into_owned()),
+            "slug" if !value.is_empty() => slugs.push(IngredientSlug::from(value.as_ref())),
+            _ => {}
+        }
+    }
+
+    let ingredients = if slugs.is_empty() {
+        vec![]
+    } else {
+        searcher
+            .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(slugs.len()))
+            .map_err(|_| error::ErrorInternalServerError("failed to search ingredients"))?
+    };
+    let added = shopping_lists.add(shelf.uid(), recipe.as_deref(), &ingredients)?;
+
+    let flash = match recipe {
+        Some(recipe) => format!(
+            "Added {} ingredients for {} to your shopping list",
+            added, recipe
+        ),
+        None => format!("Added {} ingredients to your shopping list", added),
+    };
+    Ok(FlashResponse::new(
+        Some(flash),
+        redirect.as_deref().unwrap_or("/shopping-list"),
+    ))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ShoppingListForm {
+    /// The item to change, all of them if missing
+    slug: Option<String>,
+}
+
+/// Move items from the shopping list onto the shelf
+pub(crate) async fn mark_bought(
+    form: web::Form<ShoppingListForm>,
+    shelf: Shelf,
+    shopping_lists: ShoppingLists,
+) -> Result<FlashResponse, Error> {
+    let bought: Vec<_> = shopping_lists
+        .remove(shelf.uid(), form.slug.as_deref())?
+        .into_iter()
+        .map(|item| item.ingredient)
+        .collect();
+    shelf.add_ingredients(&shelf::Bucket::Ingredients, &bought)?;
+
+    let flash = match bought.as_slice() {
+        [] => "That is not on your shopping list".to_string(),
+        [ingredient] => format!("Added {} to your shelf", ingredient.name),
+        bought => format!("Added {} ingredients to your shelf", bought.len()),
+    };
+    Ok(FlashResponse::new(Some(flash), "/shopping-list"))
+}
+
+/// Take items off the shopping list without adding them to the shelf
+pub(crate) async fn remove_from_shopping_list(
+    form: web::Form<ShoppingListForm>,
+    shelf: Shelf,
+    shopping_lists: ShoppingLists,
+) -> Result<FlashResponse, Error> {
+    let removed = shopping_lists.remove(shelf.uid(), form.slug.as_deref())?;
+
+    let flash = match removed.as_slice() {
+        [] => None,
+        [item] => Some(format!(
+            "Removed {} from your shopping list",
+            item.ingredient.name
+        )),
+        _ => Some("Cleared your shopping list".to_string()),
+    };
+    Ok(FlashResponse::new(flash, "/shopping-list"))
+}
+
+/// How many days are planned when not chosen
+const DEFAULT_PLAN_DAYS: usize = 7;
+/// The most days that can be planned at once
+const MAX_PLAN_DAYS: usize = 14;
+
+#[derive(Deserialize)]
+pub(crate) struct MealPlanForm {
+    days: Option<usize>,
+}
+
+impl MealPlanForm {
+    fn days(&self) -> usize {
+        self.days
+            .unwrap_or(DEFAULT_PLAN_DAYS)
+            .clamp(1, MAX_PLAN_DAYS)
+    }
+}
+
+/// Plan recipes for the coming days from the shelf
+fn plan_meals(
+    searcher: &bareshelf::Searcher,
+    shelf: &Shelf,
+    recipe_lists: &RecipeLists,
+    diet_bans: &DietBans,
+    days: usize,
+) -> Result<Option<bareshelf::MealPlan>, Error> {
+    if shelf
+        .get_ingredients(&shelf::Bucket::Ingredients)?
+        .is_empty()
+    {
+        return Ok(None);
+    }
+    let query = shelf_recipe_query(shelf, &use_up_weights(shelf)?, recipe_lists, diet_bans)?;
+    searcher
+        .meal_plan(query, days)
+        .map(Some)
+        .map_err(|_| error::ErrorInternalServerError("failed to search"))
+}
+
+/// A recipe for each of the coming days and everything to buy for them
+pub(crate) async fn meal_plan(
+    tera: web::Data<tera::Tera>,
+    searcher: web::Data<bareshelf::Searcher>,
+    query: web::Query<MealPlanForm>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let days = query.days();
+    let mut ctx = tera::Context::new();
+    ctx.insert("undo_hide", &flash.undo_hide());
+    ctx.insert("flash", &flash.take());
+    ctx.insert("days", &days);
+    ctx.insert("max_days", &MAX_PLAN_DAYS);
+
+    let diet_bans = diet_bans(&searcher)?;
+    if let Some(plan) = plan_meals(&searcher, &shelf, &recipe_lists, &diet_bans, days)? {
+        let favourites = recipe_lists.favourites(shelf.uid())?;
+        let shopping_list: Vec<_> = plan
+            .shopping_list()
+            .into_iter()
+            .map(|(ingredient, _)| {
+                let recipes = plan
+                    .recipes()
+                    .iter()
+                    .filter(|recipe| recipe.missing_ingredients.contains(&ingredient.slug))
+                    .map(|recipe| recipe.recipe.title.clone())
+                    .collect();
+                ShoppingListItem::from(ShoppingItem {
+                    ingredient,
+                    recipes,
+                })
+            })
+            .collect();
+        ctx.insert(
+            "recipes",
+            &plan
+                .recipes()
+                .iter()
+                .map(|recipe| {
+                    RecipeSearchResult::from(recipe)
+                        .with_favourites(&favourites)
+                        .with_diets(&diet_bans)
+                })
+                .collect::<Vec<_>>(),
+        );
+        ctx.insert("shopping_list", &shopping_list);
+    }
+
+    render(tera, "meal-plan.html", Some(&ctx))
+}
+
+/// Add everything missing for the meal plan to the shopping list
+pub(crate) async fn shop_for_meal_plan(
+    form: web::Form<MealPlanForm>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    (shopping_lists, recipe_lists): (ShoppingLists, RecipeLists),
+) -> Result<FlashResponse, Error> {
+    let mut added = 0;
+    let diet_bans = diet_bans(&searcher)?;
+    if let Some(plan) = plan_meals(&searcher, &shelf, &recipe_lists, &diet_bans, form.days())? {
+        for recipe in plan.recipes() {
+            let missing: Vec<_> = recipe
+                .recipe
+                .ingredients
+                .iter()
+                .filter(|ingredient| recipe.missing_ingredients.contains(&ingredient.slug))
+                .cloned()
+                .collect();
+            added += shopping_lists.add(shelf.uid(), Some(&recipe.recipe.title), &missing)?;
+        }
+    }
+
+    Ok(FlashResponse::new(
+        Some(format!(
+            "Added {} ingredients for your meal plan to your shopping list",
+            added
+        )),
+        "/shopping-list",
+    ))
+}
+
+/// Favourite recipes matched against the shelf, and recipes cooked recently
+pub(crate) async fn favourites(
+    tera: web::Data<tera::Tera>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+    flash: FlashMessage,
+) -> Result<HttpResponse, Error> {
+    let favourite_recipes = recipe_lists.favourites(shelf.uid())?;
+    let now = sharing::now();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("undo_hide", &flash.undo_hide());
+    ctx.insert("flash", &flash.take());
+
+    if !favourite_recipes.is_empty() {
+        let slugs: Vec<String> = favourite_recipes
+            .iter()
+            .map(|favourite| favourite.slug.clone())
+            .collect();
+        // Only the shelf ingredients, so that no favourite is left out
+        let query = RecipeQuery::default()
+            .shelf_ingredients(&ingredient_slugs(
+                &shelf.get_ingredients(&shelf::Bucket::Ingredients)?,
+            ))
+            .recipes(&slugs)
+            .limit(slugs.len());
+        let diet_bans = diet_bans(&searcher)?;
+        let mut recipes = searcher
+            .recipes(query)
+            .map_err(|_| error::ErrorInternalServerError("failed to search"))?
+            .all()
+            .iter()
+            .map(|recipe| {
+                (
+                    recipe.missing_ingredients.len(),
+                    RecipeSearchResult::from(recipe)
+                        .with_favourites(&favourite_recipes)
+                        .with_diets(&diet_bans),
+                )
+            })
+            .collect::<Vec<_>>();
+        recipes.sort_by_key(|(missing, _)| *missing);
+        ctx.insert(
+            "recipes",
+            &recipes
+                .into_iter()
+                .map(|(_, recipe)| recipe)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    ctx.insert("hidden", &recipe_lists.hidden(shelf.uid())?);
+    ctx.insert(
+        "cooked",
+        &recipe_lists
+            .cooked(shelf.uid())?
+            .iter()
+            .take(20)
+            .map(|cooked| CookedRecipe::new(cooked, now))
+            .collect::<Vec<_>>(),
+    );
+
+    render(tera, "favourites.html", Some(&ctx))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RecipeForm {
+    slug: String,
+    title: String,
+    redirect: Option<String>,
+}
+
+/// Add a recipe to the shelf's favourites or take it off
+pub(crate) async fn toggle_favourite(
+    form: web::Form<RecipeForm>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+) -> Result<FlashResponse, Error> {
+    let flash = if recipe_lists.toggle_favourite(shelf.uid(), &form.slug, &form.title)? {
+        format!("Added {} to your favourites", form.title)
+    } else {
+        format!("Removed {} from your favourites", form.title)
+    };
+
+    Ok(FlashResponse::new(
+        Some(flash),
+        form.redirect.as_deref().unwrap_or("/favourites"),
+    ))
+}
+
+/// Record that a recipe was cooked so it is suggested less for a while
+pub(crate) async fn cooked(
+    form: web::Form<RecipeForm>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+) -> Result<FlashResponse, Error> {
+    recipe_lists.add_cooked(
+        shelf.uid(),
+        Cooked {
+            slug: form.slug.clone(),
+            title: form.title.clone(),
+            at: sharing::now(),
+        },
+    )?;
+
+    Ok(FlashResponse::new(
+        Some(format!(
+            "Enjoy your {}! It will be suggested less for a while",
+            form.title
+        )),
+        form.redirect.as_deref().unwrap_or("/"),
+    ))
+}
+
+/// Stop suggesting a recipe
+///
+/// The next page offers to undo hiding it.
+pub(crate) async fn hide_recipe(
+    form: web::Form<RecipeForm>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+) -> Result<FlashResponse, Error> {
+    let redirect = form.redirect.as_deref().unwrap_or("/");
+    recipe_lists.hide(shelf.uid(), &form.slug, &form.title)?;
+
+    Ok(FlashResponse::new(
+        Some(format!("{} won't be suggested again", form.title)),
+        redirect,
+    )
+    .with_undo_hide(UndoHide {
+        slug: form.slug.clone(),
+        redirect: redirect.to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UnhideForm {
+    slug: String,
+    redirect: Option<String>,
+}
+
+/// Suggest a hidden recipe again
+pub(crate) async fn unhide_recipe(
+    form: web::Form<UnhideForm>,
+    shelf: Shelf,
+    recipe_lists: RecipeLists,
+) -> Result<FlashResponse, Error> {
+    let flash = recipe_lists
+        .unhide(shelf.uid(), &form.slug)?
+        .map(|recipe| format!("{} will be suggested again", recipe.title));
+
+    Ok(FlashResponse::new(
+        flash,
+        form.redirect.as_deref().unwrap_or("/favourites"),
+    ))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UndoForm {
+    redirect: Option<String>,
+}
+
+/// Undo the session's newest change to the shelf, leaving other household members' changes
+pub(crate) async fn undo(form: web::Form<UndoForm>, shelf: Shelf) -> Result<FlashResponse, Error> {
+    let flash = match shelf.undo()? {
+        Some(entry) => format!("Undone: {}", views::describe_changes(&entry.changes)),
+        None => "You have nothing left to undo".to_string(),
+    };
+
+    Ok(FlashResponse::new(
+        Some(flash),
+        form.redirect.as_deref().unwrap_or("/history"),
+    ))
+}
+
+/// The name a member gave, falling back to their account's email address
+fn member_name(name: Option<&str>, session: &Session) -> Result<Option<String>, Error> {
+    match name.map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) => Ok(Some(name.to_string())),
+        None => session_account(session),
+    }
+}
+
+/// Leave a household, going back to the shelf used before joining it
+pub(crate) async fn leave_household(
+    shelf: Shelf,
+    households: Households,
+    session: Session,
+) -> Result<FlashResponse, Error> {
+    let flash = match households.leave(shelf.uid(), shelf.member()) {
+        Ok(Some(member)) => {
+            session
+                .insert("uid", member.home)
+                .map_err(|_| error::ErrorInternalServerError("failed to update shelf"))?;
+            "You have left the shared shelf".to_string()
+        }
+        Ok(None) => "You are not a member of a shared shelf".to_string(),
+        Err(crate::error::Error::Other(message)) => message,
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(FlashResponse::new(Some(flash), "/share-shelf"))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RevokeShare {
+    id: u64,
+}
+
+pub(crate) async fn revoke_share(
+    form: web::Form<RevokeShare>,
+    shelf: Shelf,
+    share_tokens: ShareTokens,
+) -> Result<FlashResponse, Error> {
+    let flash = if share_tokens.revoke(shelf.uid(), form.id)? {
+        "Share link revoked"
+    } else {
+        "Share link not found"
+    };
+
+    Ok(FlashResponse::new(Some(flash.to_string()), "/share-shelf"))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SharedShelfQuery {
+    token: String,
+}
+
+/// Read-only view of someone else's shelf and its recipes
+///
+/// This never touches the visitor's own shelf.
+pub(crate) async fn shared_shelf(
+    tera: web::Data<tera::Tera>,
+    searcher: web::Data<bareshelf::Searcher>,
+    shelf: Shelf,
+    query: web::Query<SharedShelfQuery>,
+    share_tokens: ShareTokens,
+    recipe_lists: RecipeLists,
+    app_data: web::Data<crate::AppData>,
+) -> Result<Either<HttpResponse, FlashResponse>, Error> {
+    let share_token = match share_tokens.validate(app_data.cookie_key.encryption(), &query.token) {
+        Ok(share_token) => share_token,
+        Err(err) => {
+            return Ok(Either::Right(FlashResponse::new(
+                Some(err.to_string()),
+                "/",
+            )))
+        }
+    };
+    let shared = shelf.for_uid(share_token.uid);
+
+    let mut ctx = tera::Context::new();
+    ctx.insert(
+        "ingredients",
+        &shared.get_ingredients(&shelf::Bucket::Ingredients)?,
+    );
+    let own_shelf = share_token.uid == shelf.uid();
+    ctx.insert("own_shelf", &own_shelf);
+    ctx.insert(
+        "can_join",
+        &(share_token.permission == Permission::Join && !own_shelf),
+    );
+    ctx.insert("token", &query.token);
+    insert_shelf_recipes(&mut ctx, &searcher, &shared, &recipe_lists, None)?;
+
+    render(tera, "shared-shelf.html", Some(&ctx)).map(Either::Left)
+}
+
+fn render(
     tmpl: web::Data<tera::Tera>,
     template_name: &str,
     context: Option<&tera::Context>,
@@ -274,11 +1792,21 @@ fn render(
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
-// TODO: figure out a better way of handling this.
-// Two options I can think of:
-// 1. Replace or fix the autocomplete library so that we always get a slug rather than sometimes a
-//    prefix
-// 2. Create a web specific searcher type that handles how searching is done in the web UI
+fn get_ingredient_by_slug(
+    searcher: &bareshelf::Searcher,
+    slug: &str,
+) -> Result<Option<bareshelf::Ingredient>, Error> {
+    let slugs = [IngredientSlug::from(slug)];
+    let mut ingredients = searcher
+        .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(1))
+        .map_err(|_| error::ErrorInternalServerError("failed to search ingredients"))?;
+
+    Ok(ingredients.pop())
+}
+
+/// Autocomplete suggestions for a bucket
+///
+/// Adding an ingredient goes through `IngredientResolver` so suggestions never get added blindly.
 fn get_ingredients_by_prefix(
     shelf: &Shelf,
     searcher: &bareshelf::Searcher,
@@ -295,3 +1823,301 @@ fn get_ingredients_by_prefix(
 
     Ok(ingredients)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{
+        cookie::{Cookie, Key},
+        test, App,
+    };
+    use bareshelf::Ingredient;
+
+    use super::*;
+    use crate::{
+        accounts::account_member,
+        shelf_store::{ShelfStore, SledShelfStore},
+    };
+
+    /// The account and sharing routes, storing everything in `sled`
+    macro_rules! app {
+        ($sled:expr, $key:expr, $store:expr) => {
+            test::init_service(
+                App::new()
+                    .wrap(SessionMiddleware::new(
+                        CookieSessionStore::default(),
+                        $key.clone(),
+                    ))
+                    .app_data(web::Data::new(crate::AppData {
+                        cookie_key: $key.clone(),
+                    }))
+                    .app_data(web::Data::new($sled.clone()))
+                    .app_data(web::Data::from($store.clone()))
+                    .route("/signup", web::post().to(signup))
+                    .route("/login", web::post().to(login))
+                    .route("/share-shelf", web::post().to(create_share))
+                    .route("/share-shelf/accept", web::post().to(accept_share)),
+            )
+            .await
+        };
+        // the shelf routes, searching `$searcher`
+        ($sled:expr, $key:expr, $store:expr, $searcher:expr) => {
+            test::init_service(
+                App::new()
+                    .wrap(SessionMiddleware::new(
+                        CookieSessionStore::default(),
+                        $key.clone(),
+                    ))
+                    .app_data(web::Data::new(crate::templates().unwrap()))
+                    .app_data(web::Data::new($searcher.clone()))
+                    .app_data(web::Data::new($sled.clone()))
+                    .app_data(web::Data::from($store.clone()))
+                    .route("/signup", web::post().to(signup))
+                    .route("/add-ingredient", web::post().to(add_ingredient))
+                    .route("/import-shelf", web::post().to(review_import_shelf)),
+            )
+            .await
+        };
+    }
+
+    /// Someone using the site, with their session cookie
+    #[derive(Default)]
+    struct Browser {
+        cookie: Option<Cookie<'static>>,
+    }
+
+    /// Post a form from a `Browser`, keeping the session cookie it sets
+    macro_rules! post {
+        ($app:expr, $browser:expr, $path:expr, $body:expr) => {{
+            let mut request = test::TestRequest::post()
+                .uri($path)
+                .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+                .set_payload($body);
+            if let Some(cookie) = $browser.cookie.clone() {
+                request = request.cookie(cookie);
+            }
+            let response = test::call_service(&$app, request.to_request()).await;
+            assert!(response.status().is_redirection(), "{}", response.status());
+            if let Some(cookie) = response.response().cookies().next() {
+                $browser.cookie = Some(cookie.into_owned());
+            }
+        }};
+    }
+
+    fn setup() -> (sled::Db, Key, Arc<dyn ShelfStore>) {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        let store: Arc<dyn ShelfStore> = Arc::new(SledShelfStore::new(&sled).unwrap());
+        (sled, Key::generate(), store)
+    }
+
+    /// A search index of a few ingredients, deleted when the `TempDir` is dropped
+    fn searcher() -> (tempfile::TempDir, bareshelf::Searcher) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut indexer = bareshelf::indexer(dir.path()).unwrap();
+        for (name, slug) in &[("Egg", "egg"), ("Butter", "butter"), ("Salt", "salt")] {
+            indexer.add_ingredient(Ingredient::new(name, slug));
+        }
+        indexer.commit().unwrap();
+        let searcher = bareshelf::searcher(dir.path()).unwrap();
+        (dir, searcher)
+    }
+
+    /// The only share token issued so far
+    fn issued_token(sled: &sled::Db) -> ShareToken {
+        let (_, token) = sled
+            .open_tree("share_tokens")
+            .unwrap()
+            .iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        serde_json::from_slice(&token).unwrap()
+    }
+
+    fn join_form(key: &Key, token: &ShareToken) -> String {
+        format!(
+            "token={}&action=join&name=Member",
+            encode_share_token(key.encryption(), token).unwrap()
+        )
+    }
+
+    fn account_uid(sled: &sled::Db, email: &str) -> shelf::Uid {
+        Accounts::new(sled)
+            .unwrap()
+            .get(email)
+            .unwrap()
+            .unwrap()
+            .uid
+    }
+
+    fn ingredients(store: &Arc<dyn ShelfStore>, uid: shelf::Uid) -> Vec<String> {
+        ingredient_slugs(
+            &Shelf::new(store.clone(), uid, 0)
+                .get_ingredients(&shelf::Bucket::Ingredients)
+                .unwrap(),
+        )
+    }
+
+    fn add_egg(store: &Arc<dyn ShelfStore>, uid: shelf::Uid) {
+        Shelf::new(store.clone(), uid, 0)
+            .add_ingredient(&shelf::Bucket::Ingredients, &Ingredient::new("Egg", "egg"))
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn logging_in_from_another_account_keeps_its_shelf() {
+        let (sled, key, store) = setup();
+        let app = app!(sled, key, store);
+        let mut first = Browser::default();
+        let mut second = Browser::default();
+
+        post!(
+            app,
+            first,
+            "/signup",
+            "email=first@example.org&password=password1"
+        );
+        post!(
+            app,
+            second,
+            "/signup",
+            "email=second@example.org&password=password2"
+        );
+        let (first_uid, second_uid) = (
+            account_uid(&sled, "first@example.org"),
+            account_uid(&sled, "second@example.org"),
+        );
+        add_egg(&store, first_uid);
+
+        post!(
+            app,
+            first,
+            "/login",
+            "email=second@example.org&password=password2"
+        );
+
+        assert_eq!(ingredients(&store, first_uid), vec!["egg"]);
+        assert!(ingredients(&store, second_uid).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn logging_in_from_a_household_keeps_its_shelf() {
+        let (sled, key, store) = setup();
+        let app = app!(sled, key, store);
+        let mut owner = Browser::default();
+        let mut member = Browser::default();
+        let mut other = Browser::default();
+
+        post!(app, owner, "/share-shelf", "permission=join&days=7");
+        let token = issued_token(&sled);
+        add_egg(&store, token.uid);
+        post!(app, member, "/share-shelf/accept", join_form(&key, &token));
+        post!(
+            app,
+            other,
+            "/signup",
+            "email=account@example.org&password=password1"
+        );
+
+        post!(
+            app,
+            member,
+            "/login",
+            "email=account@example.org&password=password1"
+        );
+
+        assert_eq!(ingredients(&store, token.uid), vec!["egg"]);
+        assert!(ingredients(&store, account_uid(&sled, "account@example.org")).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn household_members_keep_their_place_once_signed_up() {
+        let (sled, key, store) = setup();
+        let app = app!(sled, key, store);
+        let mut owner = Browser::default();
+        let mut member = Browser::default();
+
+        post!(app, owner, "/share-shelf", "permission=join&days=7");
+        let token = issued_token(&sled);
+        post!(app, member, "/share-shelf/accept", join_form(&key, &token));
+        post!(
+            app,
+            member,
+            "/signup",
+            "email=member@example.org&password=password1"
+        );
+
+        let household = Households::new(&sled)
+            .unwrap()
+            .get(token.uid)
+            .unwrap()
+            .unwrap();
+        assert_eq!(household.members.len(), 2);
+        let member = household
+            .member(account_member("member@example.org"))
+            .unwrap();
+        assert_eq!(member.name, "Member");
+        assert_ne!(member.home, token.uid);
+        assert_eq!(account_uid(&sled, "member@example.org"), member.home);
+    }
+
+    #[actix_web::test]
+    async fn add_ingredients_with_punctuation() {
+        let (sled, key, store) = setup();
+        let (_dir, searcher) = searcher();
+        let app = app!(sled, key, store, searcher);
+        let mut browser = Browser::default();
+        post!(
+            app,
+            browser,
+            "/signup",
+            "email=cook@example.org&password=password1"
+        );
+
+        for input in &["cheese%3A", "eggs+(large", "egg%3A", "%22salt", "(butter)"] {
+            post!(
+                app,
+                browser,
+                "/add-ingredient",
+                format!("ingredient={}&bucket=ingredients", input)
+            );
+        }
+
+        assert_eq!(
+            ingredients(&store, account_uid(&sled, "cook@example.org")),
+            vec!["butter", "egg", "salt"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn review_an_import_with_punctuation() {
+        let (sled, key, store) = setup();
+        let (_dir, searcher) = searcher();
+        let app = app!(sled, key, store, searcher);
+
+        let request = test::TestRequest::post()
+            .uri("/import-shelf")
+            .set_form([
+                (
+                    "list",
+                    "butter (unsalted\nsalt: to taste\n\"eggs\"\ncheese:",
+                ),
+                ("bucket", "ingredients"),
+            ])
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success(), "{}", response.status());
+
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.contains("&quot;eggs&quot; &rarr; Egg"), "{}", body);
+        for line in &["butter (unsalted", "salt: to taste", "cheese:"] {
+            assert!(
+                body.contains(&format!("<span class=missing-ingredient>{}</span>", line)),
+                "{}",
+                line
+            );
+        }
+    }
+}
@@ -1,10 +1,62 @@
-use crate::error::Error;
+use std::{
+    convert::TryInto,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use rand::Rng;
 use ring::hmac;
+use serde::{Deserialize, Serialize};
 
-pub(crate) fn encode_share_token(secret: &[u8], uid: u32) -> Result<String, Error> {
+use crate::{error::Error, shelf::Uid};
+
+/// What the holder of a share token can do with the shelf
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Permission {
+    /// See the shelf and its recipes without changing anything
+    View,
+    /// Use the shelf as their own, including editing it
+    Join,
+}
+
+/// The signed payload of a share link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ShareToken {
+    /// Random id used to revoke the token
+    pub(crate) id: u64,
+    pub(crate) uid: Uid,
+    pub(crate) permission: Permission,
+    /// Unix timestamp in seconds after which the token is no longer valid
+    pub(crate) expires: u64,
+}
+
+impl ShareToken {
+    pub(crate) fn new(uid: Uid, permission: Permission, valid_for_days: u64) -> Self {
+        Self {
+            id: rand::thread_rng().gen(),
+            uid,
+            permission,
+            expires: now() + valid_for_days * 24 * 60 * 60,
+        }
+    }
+
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expires <= now
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs()
+}
+
+pub(crate) fn encode_share_token(secret: &[u8], token: &ShareToken) -> Result<String, Error> {
     let message =
-        serde_json::to_vec(&uid).map_err(|_| Error::Other("cannot create token".to_string()))?;
+        serde_json::to_vec(token).map_err(|_| Error::Other("cannot create token".to_string()))?;
 
     let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA384, secret), &message);
     let token = format!(
@@ -23,7 +75,12 @@ fn invalid_token<T>(_: T) -> Error {
 
 struct Nothing;
 
-pub(crate) fn decode_share_token(secret: &[u8], token: &str) -> Result<u32, Error> {
+/// Decode a share token, checking its signature and expiry but not whether it was revoked
+pub(crate) fn decode_share_token(
+    secret: &[u8],
+    token: &str,
+    now: u64,
+) -> Result<ShareToken, Error> {
     let parts: Vec<_> = token.split('.').collect();
     if parts.len() != 2 {
         return Err(invalid_token(Nothing));
@@ -34,24 +91,140 @@ pub(crate) fn decode_share_token(secret: &[u8], token: &str) -> Result<u32, Erro
     );
     hmac::verify(&hmac::Key::new(hmac::HMAC_SHA384, secret), &message, &tag)
         .map_err(invalid_token)?;
-    let uid: u32 = serde_json::from_slice(&message).map_err(invalid_token)?;
+    let token: ShareToken = match serde_json::from_slice(&message) {
+        Ok(token) => token,
+        // links made before tokens could expire were signed over just the uid
+        Err(_) if serde_json::from_slice::<Uid>(&message).is_ok() => {
+            return Err(Error::Other(
+                "this share link format is no longer supported, ask for a new link".to_string(),
+            ))
+        }
+        Err(err) => return Err(invalid_token(err)),
+    };
+    if token.is_expired(now) {
+        return Err(Error::Other("this share link has expired".to_string()));
+    }
+
+    Ok(token)
+}
+
+/// Share tokens that have been handed out, and those that have been revoked
+pub(crate) struct ShareTokens {
+    issued: sled::Tree,
+    revoked: sled::Tree,
+}
+
+impl ShareTokens {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            issued: sled.open_tree("share_tokens")?,
+            revoked: sled.open_tree("revoked_share_tokens")?,
+        })
+    }
+
+    pub(crate) fn issue(&self, token: &ShareToken) -> Result<(), Error> {
+        self.issued
+            .insert(issued_key(token.uid, token.id), serde_json::to_vec(token)?)?;
+        Ok(())
+    }
+
+    /// Unexpired tokens issued for a shelf
+    pub(crate) fn issued(&self, uid: Uid, now: u64) -> Result<Vec<ShareToken>, Error> {
+        let mut tokens = vec![];
+        for item in self.issued.scan_prefix(format!("/{}/", uid)) {
+            let (key, value) = item?;
+            let token: ShareToken = serde_json::from_slice(&value)?;
+            if token.is_expired(now) {
+                self.issued.remove(key)?;
+            } else {
+                tokens.push(token);
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Revoke a token issued for the shelf `uid`
+    ///
+    /// Returns false if there is no such token.
+    pub(crate) fn revoke(&self, uid: Uid, id: u64) -> Result<bool, Error> {
+        match self.issued.remove(issued_key(uid, id))? {
+            Some(token) => {
+                let token: ShareToken = serde_json::from_slice(&token)?;
+                self.revoked
+                    .insert(id.to_be_bytes(), &token.expires.to_be_bytes())?;
+                self.prune_revoked(now())?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Forget revoked tokens that have expired since, they are rejected anyway
+    fn prune_revoked(&self, now: u64) -> Result<(), Error> {
+        for item in self.revoked.iter() {
+            let (id, expires) = item?;
+            let expires = u64::from_be_bytes(
+                expires
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| Error::Other("invalid revoked token".to_string()))?,
+            );
+            if expires <= now {
+                self.revoked.remove(id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_revoked(&self, token: &ShareToken) -> Result<bool, Error> {
+        Ok(self.revoked.contains_key(token.id.to_be_bytes())?)
+    }
+
+    /// Decode a token and check that it has not been revoked
+    pub(crate) fn validate(&self, secret: &[u8], token: &str) -> Result<ShareToken, Error> {
+        let token = decode_share_token(secret, token, now())?;
+        if self.is_revoked(&token)? {
+            return Err(Error::Other("this share link has been revoked".to_string()));
+        }
+        Ok(token)
+    }
+}
+
+impl FromRequest for ShareTokens {
+    type Error = Error;
+    type Future = Ready<Result<ShareTokens, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
+        ready(ShareTokens::new(sled.get_ref()))
+    }
+}
 
-    Ok(uid)
+fn issued_key(uid: Uid, id: u64) -> String {
+    format!("/{}/{}", uid, id)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn token() -> ShareToken {
+        ShareToken {
+            id: 42,
+            uid: 1234,
+            permission: Permission::View,
+            expires: 1000,
+        }
+    }
+
     #[test]
     fn encode_and_decode_a_token() {
         let secret = "a secret".as_bytes();
-        let uid = 1234;
 
-        let token = encode_share_token(&secret, uid).unwrap();
-        let decoded_uid = decode_share_token(&secret, &token).unwrap();
+        let encoded = encode_share_token(secret, &token()).unwrap();
+        let decoded = decode_share_token(secret, &encoded, 999).unwrap();
 
-        assert_eq!(uid, decoded_uid);
+        assert_eq!(token(), decoded);
     }
 
     #[test]
@@ -59,11 +232,70 @@ mod tests {
         let secret = "a secret".as_bytes();
         let bad_token = "a bad token";
 
-        let result = decode_share_token(&secret, &bad_token);
+        let result = decode_share_token(secret, bad_token, 0);
         assert!(result.is_err());
         assert_eq!(
             format!("{}", invalid_token(Nothing)),
             format!("{}", result.err().unwrap())
         );
     }
+
+    #[test]
+    fn fail_decoding_an_expired_token() {
+        let secret = "a secret".as_bytes();
+
+        let encoded = encode_share_token(secret, &token()).unwrap();
+
+        assert!(decode_share_token(secret, &encoded, 1000).is_err());
+    }
+
+    #[test]
+    fn revoke_a_token() {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        let tokens = ShareTokens::new(&sled).unwrap();
+        let secret = "a secret".as_bytes();
+        let token = ShareToken::new(1234, Permission::Join, 7);
+        let encoded = encode_share_token(secret, &token).unwrap();
+
+        tokens.issue(&token).unwrap();
+        assert_eq!(tokens.issued(1234, now()).unwrap(), vec![token.clone()]);
+        assert!(tokens.validate(secret, &encoded).is_ok());
+
+        assert!(!tokens.revoke(4321, token.id).unwrap());
+        assert!(tokens.revoke(1234, token.id).unwrap());
+        assert!(tokens.issued(1234, now()).unwrap().is_empty());
+        assert!(tokens.validate(secret, &encoded).is_err());
+    }
+
+    #[test]
+    fn forget_revoked_tokens_once_expired() {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        let tokens = ShareTokens::new(&sled).unwrap();
+        let expired = token();
+        let current = ShareToken::new(1234, Permission::View, 7);
+
+        tokens.issue(&expired).unwrap();
+        tokens.issue(&current).unwrap();
+        assert!(tokens.revoke(1234, expired.id).unwrap());
+        assert!(tokens.revoke(1234, current.id).unwrap());
+
+        assert!(!tokens.is_revoked(&expired).unwrap());
+        assert!(tokens.is_revoked(&current).unwrap());
+    }
+
+    #[test]
+    fn reject_a_token_in_the_old_format() {
+        let secret = "a secret".as_bytes();
+        let uid: Uid = 1234;
+        let message = serde_json::to_vec(&uid).unwrap();
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA384, secret), &message);
+        let old = format!(
+            "{}.{}",
+            base64::encode_config(&message, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(tag.as_ref(), base64::URL_SAFE_NO_PAD)
+        );
+
+        let err = decode_share_token(secret, &old, 0).unwrap_err();
+        assert!(err.to_string().contains("no longer supported"));
+    }
 }
@@ -1,37 +1,109 @@
+use std::{collections::HashMap, sync::Arc};
+
 use actix_session::SessionExt;
 use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
-use futures::future::{ok, Ready};
+use futures::future::{ready, Ready};
 use rand::Rng;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use bareshelf::Ingredient;
+use bareshelf::{Allergen, Ingredient, KeyIngredientMode};
+use chrono::NaiveDate;
 
-use crate::error::Error;
+use crate::{
+    diets::Diet,
+    error::Error,
+    history::{self, BucketChange, HistoryEntry},
+    households::{session_member, MemberId},
+    sharing,
+    shelf_store::ShelfStore,
+};
 
 pub(crate) fn ingredient_slugs(ingredients: &[Ingredient]) -> Vec<String> {
     ingredients.iter().map(Ingredient::slug).collect()
 }
 
+/// Identifies a shelf
+///
+/// Random 128 bit uids do not collide. Uids from before accounts were only 32 bits and still
+/// deserialize from old session cookies.
+pub(crate) type Uid = u128;
+
+/// How many days before its best before date an ingredient starts to be prioritised
+pub(crate) const USE_UP_DAYS: i64 = 7;
+
+/// How urgently an ingredient should be used up
+///
+/// From 0 when it has `USE_UP_DAYS` or more left, up to 1 on or after its best before date.
+pub(crate) fn use_up_weight(best_before: NaiveDate, today: NaiveDate) -> f32 {
+    let days_left = (best_before - today).num_days().clamp(0, USE_UP_DAYS);
+    (USE_UP_DAYS - days_left) as f32 / USE_UP_DAYS as f32
+}
+
+/// Preferences that apply to everyone searching with a shelf
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ShelfSettings {
+    /// Diets the shelf's recipes must keep to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) diets: Vec<Diet>,
+    /// Allergens the shelf's recipes must not contain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allergens: Vec<Allergen>,
+    /// How many of the key ingredients recipes need
+    #[serde(default)]
+    pub(crate) key_ingredient_mode: KeyIngredientMode,
+}
+
+/// The outcome of `Shelf::merge_from`
+#[derive(Debug, Default)]
+pub(crate) struct MergeReport {
+    pub(crate) added: usize,
+    /// Ingredients where the other shelf disagreed and this shelf's choice was kept
+    pub(crate) conflicts: Vec<Ingredient>,
+}
+
+/// The last change made to an ingredient in a bucket, and who made it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LastChange {
+    pub(crate) member: MemberId,
+    pub(crate) bucket: Bucket,
+    pub(crate) ingredient: Ingredient,
+    pub(crate) removed: bool,
+    /// Unix timestamp in seconds
+    pub(crate) at: u64,
+}
+
 pub(crate) struct Shelf {
-    sled: sled::Db, // TODO: replace this with a trait if testing becomes slow
-    uid: u32,
+    store: Arc<dyn ShelfStore>,
+    uid: Uid,
+    /// Who is making changes through this shelf
+    member: MemberId,
 }
 
 impl Shelf {
+    pub(crate) fn new(store: Arc<dyn ShelfStore>, uid: Uid, member: MemberId) -> Self {
+        Self { store, uid, member }
+    }
+
     pub(crate) fn add_ingredient(
         &self,
         bucket: &Bucket,
         ingredient: &Ingredient,
     ) -> Result<bool, Error> {
-        let mut ingredients = self.get_ingredients(bucket)?;
-        if !ingredients.iter().any(|i| i == ingredient) {
-            ingredients.push(ingredient.clone());
-            ingredients.sort_unstable();
-            self.set_ingredients(bucket, ingredients)?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        Ok(self.add_ingredients(bucket, std::slice::from_ref(ingredient))? == 1)
+    }
+
+    /// Add several ingredients to a bucket in a single write
+    ///
+    /// Returns how many of them were not already in the bucket.
+    pub(crate) fn add_ingredients(
+        &self,
+        bucket: &Bucket,
+        new_ingredients: &[Ingredient],
+    ) -> Result<usize, Error> {
+        let added = self.insert(bucket, new_ingredients)?;
+        let count = added.len();
+        self.record(vec![BucketChange::added(*bucket, added)])?;
+        Ok(count)
     }
 
     pub(crate) fn remove_ingredient(
@@ -39,57 +111,265 @@ impl Shelf {
         bucket: &Bucket,
         slug: &str,
     ) -> Result<Option<Ingredient>, Error> {
-        let ingredients = self.get_ingredients(bucket)?;
-        let ingredient = ingredients.iter().find(|i| i.slug == slug);
-        if let Some(ingredient) = ingredient {
-            let ingredient = ingredient.clone();
-            self.set_ingredients(
-                bucket,
-                ingredients.into_iter().filter(|i| i.slug != slug).collect(),
-            )?;
-            Ok(Some(ingredient))
-        } else {
-            Ok(None)
-        }
+        let (removed, best_before) = self.delete(bucket, &[slug])?;
+        let ingredient = removed.first().cloned();
+        self.record(vec![BucketChange::removed(*bucket, removed, best_before)])?;
+        Ok(ingredient)
     }
 
     pub(crate) fn get_ingredients(&self, bucket: &Bucket) -> Result<Vec<Ingredient>, Error> {
-        let result = self.sled.get(self.key(&bucket.session_key()).as_bytes())?;
-
-        if let Some(result) = result {
-            Ok(serde_json::from_slice(&result)?)
-        } else {
-            Ok(vec![])
-        }
+        self.store.get(self.uid, *bucket)
     }
 
+    /// Empty every bucket, as a single change that can be undone
     pub(crate) fn remove_all(&self) -> Result<(), Error> {
-        for bucket in [
-            Bucket::KeyIngredients,
-            Bucket::BannedIngredients,
-            Bucket::Ingredients,
-        ]
-        .iter()
-        {
-            self.sled.remove(self.key(&bucket.session_key()))?;
+        let mut changes = vec![];
+        for bucket in Bucket::ALL.iter() {
+            let removed = self.update(bucket, std::mem::take)?;
+            let best_before = self.take_best_before(bucket, &removed)?;
+            changes.push(BucketChange::removed(*bucket, removed, best_before));
         }
-        Ok(())
+        self.record(changes)
     }
 
-    pub(crate) fn uid(&self) -> u32 {
+    pub(crate) fn uid(&self) -> Uid {
         self.uid
     }
 
-    fn set_ingredients(&self, bucket: &Bucket, ingredients: Vec<Ingredient>) -> Result<(), Error> {
-        self.sled.insert(
-            self.key(&bucket.session_key()).as_bytes(),
-            serde_json::to_vec(&ingredients)?,
-        )?;
-        Ok(())
+    pub(crate) fn member(&self) -> MemberId {
+        self.member
+    }
+
+    /// The shelf with a different uid in the same store
+    pub(crate) fn for_uid(&self, uid: Uid) -> Shelf {
+        Shelf {
+            store: self.store.clone(),
+            uid,
+            member: self.member,
+        }
+    }
+
+    /// The last change to each ingredient that has been on the shelf, newest first
+    pub(crate) fn changes(&self) -> Result<Vec<LastChange>, Error> {
+        let mut changes = self.store.last_changes(self.uid)?;
+        changes.sort_by_key(|change| std::cmp::Reverse(change.at));
+        Ok(changes)
+    }
+
+    /// Best before dates of ingredients on the shelf, by slug
+    pub(crate) fn best_before(&self) -> Result<HashMap<String, NaiveDate>, Error> {
+        self.store.best_before(self.uid)
+    }
+
+    /// Set or clear the best before date of an ingredient on the shelf
+    pub(crate) fn set_best_before(&self, slug: &str, date: Option<NaiveDate>) -> Result<(), Error> {
+        self.store.set_best_before(self.uid, slug, date)
+    }
+
+    pub(crate) fn settings(&self) -> Result<ShelfSettings, Error> {
+        self.store.settings(self.uid)
+    }
+
+    pub(crate) fn set_settings(&self, settings: &ShelfSettings) -> Result<(), Error> {
+        self.store.set_settings(self.uid, settings)
     }
 
-    fn key(&self, path: &str) -> String {
-        format!("/{}/{}", self.uid, path)
+    /// Every change still kept in the shelf's history, newest first
+    pub(crate) fn history(&self) -> Result<Vec<HistoryEntry>, Error> {
+        self.store.history(self.uid)
+    }
+
+    /// Revert the member's newest change in the shelf's history
+    ///
+    /// Other members of a household keep their changes. Returns the change that was undone, or
+    /// `None` if the member has no history left. Undoing is not itself recorded in the history so
+    /// repeated undos walk further back.
+    pub(crate) fn undo(&self) -> Result<Option<HistoryEntry>, Error> {
+        let entry = match self.store.pop_history(self.uid, self.member)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut inverse = vec![];
+        for change in entry.changes.iter() {
+            let slugs: Vec<_> = change.added.iter().map(|i| i.slug.as_str()).collect();
+            let (removed, best_before) = self.delete(&change.bucket, &slugs)?;
+            let added = self.insert(&change.bucket, &change.removed)?;
+            for ingredient in added.iter() {
+                if let Some(date) = change.best_before.get(&ingredient.slug) {
+                    self.set_best_before(&ingredient.slug, Some(*date))?;
+                }
+            }
+            inverse.push(BucketChange {
+                bucket: change.bucket,
+                added,
+                removed,
+                best_before,
+            });
+        }
+        self.record_last_changes(&inverse, sharing::now())?;
+        Ok(Some(entry))
+    }
+
+    /// Copy everything from another shelf into this one
+    ///
+    /// Each bucket is a union of both shelves. An ingredient that is banned on one shelf but
+    /// wanted (on the shelf or key) on the other is a conflict; this shelf's choice is kept.
+    pub(crate) fn merge_from(&self, other_uid: Uid) -> Result<MergeReport, Error> {
+        let other = self.for_uid(other_uid);
+        let banned = self.get_ingredients(&Bucket::BannedIngredients)?;
+        let wanted: Vec<_> = self
+            .get_ingredients(&Bucket::Ingredients)?
+            .into_iter()
+            .chain(self.get_ingredients(&Bucket::KeyIngredients)?)
+            .collect();
+
+        let mut report = MergeReport::default();
+        let mut changes = vec![];
+        for bucket in Bucket::ALL.iter() {
+            let conflicting_with = match bucket {
+                Bucket::BannedIngredients => &wanted,
+                Bucket::Ingredients | Bucket::KeyIngredients => &banned,
+            };
+            let (conflicts, ingredients): (Vec<_>, Vec<_>) = other
+                .get_ingredients(bucket)?
+                .into_iter()
+                .partition(|ingredient| conflicting_with.contains(ingredient));
+
+            let added = self.insert(bucket, &ingredients)?;
+            report.added += added.len();
+            changes.push(BucketChange::added(*bucket, added));
+            for ingredient in conflicts {
+                if !report.conflicts.contains(&ingredient) {
+                    report.conflicts.push(ingredient);
+                }
+            }
+        }
+        self.record(changes)?;
+        Ok(report)
+    }
+
+    /// Add ingredients to a bucket, returning those that were not already there
+    fn insert(
+        &self,
+        bucket: &Bucket,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<Ingredient>, Error> {
+        self.update(bucket, |ingredients| {
+            let mut added = vec![];
+            for ingredient in new_ingredients {
+                if !ingredients.contains(ingredient) {
+                    ingredients.push(ingredient.clone());
+                    added.push(ingredient.clone());
+                }
+            }
+            ingredients.sort_unstable();
+            added
+        })
+    }
+
+    /// Remove ingredients from a bucket by slug, returning those that were there
+    ///
+    /// Ingredients removed from the shelf lose their best before date, which is returned with
+    /// them.
+    fn delete(
+        &self,
+        bucket: &Bucket,
+        slugs: &[&str],
+    ) -> Result<(Vec<Ingredient>, HashMap<String, NaiveDate>), Error> {
+        let removed = self.update(bucket, |ingredients| {
+            let (removed, kept) = std::mem::take(ingredients)
+                .into_iter()
+                .partition(|ingredient| slugs.contains(&ingredient.slug.as_str()));
+            *ingredients = kept;
+            removed
+        })?;
+        let best_before = self.take_best_before(bucket, &removed)?;
+        Ok((removed, best_before))
+    }
+
+    /// Clear the best before dates of ingredients taken off the shelf, returning what they were
+    fn take_best_before(
+        &self,
+        bucket: &Bucket,
+        removed: &[Ingredient],
+    ) -> Result<HashMap<String, NaiveDate>, Error> {
+        if *bucket != Bucket::Ingredients || removed.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut dates = self.best_before()?;
+        let mut taken = HashMap::new();
+        for ingredient in removed {
+            if let Some(date) = dates.remove(&ingredient.slug) {
+                self.set_best_before(&ingredient.slug, None)?;
+                taken.insert(ingredient.slug.clone(), date);
+            }
+        }
+        Ok(taken)
+    }
+
+    /// Atomically read, modify and write a bucket
+    ///
+    /// Several requests can change the same shelf at once, for example two members of a
+    /// household. If the bucket changed while `f` was running the write is abandoned and `f` is
+    /// run again on the new contents, so `f` may be called more than once.
+    fn update<T>(
+        &self,
+        bucket: &Bucket,
+        mut f: impl FnMut(&mut Vec<Ingredient>) -> T,
+    ) -> Result<T, Error> {
+        loop {
+            let current = self.get_ingredients(bucket)?;
+            let mut ingredients = current.clone();
+            let result = f(&mut ingredients);
+            if ingredients == current
+                || self
+                    .store
+                    .compare_and_swap(self.uid, *bucket, &current, &ingredients)?
+            {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Append a change to the history, ignoring buckets that did not change
+    fn record(&self, mut changes: Vec<BucketChange>) -> Result<(), Error> {
+        changes.retain(|change| !change.is_empty());
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let entry = HistoryEntry {
+            member: self.member,
+            at: sharing::now(),
+            changes,
+        };
+        self.record_last_changes(&entry.changes, entry.at)?;
+        self.store.append_history(self.uid, &entry)?;
+        self.store.prune_history(
+            self.uid,
+            entry.at.saturating_sub(history::MAX_AGE),
+            history::MAX_ENTRIES,
+        )
+    }
+
+    fn record_last_changes(&self, changes: &[BucketChange], at: u64) -> Result<(), Error> {
+        let mut last_changes = vec![];
+        for change in changes {
+            let ingredients = change
+                .added
+                .iter()
+                .map(|ingredient| (ingredient, false))
+                .chain(change.removed.iter().map(|ingredient| (ingredient, true)));
+            for (ingredient, removed) in ingredients {
+                last_changes.push(LastChange {
+                    member: self.member,
+                    bucket: change.bucket,
+                    ingredient: ingredient.clone(),
+                    removed,
+                    at,
+                });
+            }
+        }
+        self.store.set_last_changes(self.uid, &last_changes)
     }
 }
 
@@ -101,19 +381,20 @@ impl FromRequest for Shelf {
         let session = req.get_session();
         let uid = session.get("uid").unwrap_or(None).unwrap_or_else(|| {
             let mut rng = rand::thread_rng();
-            let uid = rng.gen::<u32>();
+            let uid = rng.gen::<Uid>();
             session.insert("uid", uid).unwrap();
             uid
         });
-        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
-        ok(Shelf {
-            sled: sled.get_ref().clone(),
+        let store = req.app_data::<web::Data<dyn ShelfStore>>().unwrap();
+        ready(Ok(Shelf::new(
+            store.clone().into_inner(),
             uid,
-        })
+            session_member(&session),
+        )))
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum Bucket {
     KeyIngredients,
@@ -122,6 +403,12 @@ pub(crate) enum Bucket {
 }
 
 impl Bucket {
+    pub(crate) const ALL: [Bucket; 3] = [
+        Bucket::KeyIngredients,
+        Bucket::BannedIngredients,
+        Bucket::Ingredients,
+    ];
+
     pub(crate) fn flash_name(&self) -> String {
         match self {
             Bucket::KeyIngredients => "key ingredients".to_string(),
@@ -131,7 +418,7 @@ impl Bucket {
     }
 
     /// The key to use in the session object
-    fn session_key(&self) -> String {
+    pub(crate) fn session_key(&self) -> String {
         match self {
             Bucket::KeyIngredients => "key_ingredients".to_string(),
             Bucket::BannedIngredients => "banned_ingredients".to_string(),
@@ -139,3 +426,258 @@ impl Bucket {
         }
     }
 }
+
+impl std::str::FromStr for Bucket {
+    type Err = Error;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key {
+            "key_ingredients" => Ok(Bucket::KeyIngredients),
+            "banned_ingredients" => Ok(Bucket::BannedIngredients),
+            "ingredients" => Ok(Bucket::Ingredients),
+            _ => Err(Error::Other(format!("unknown bucket {}", key))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shelf_store::{tests::stores, MemoryShelfStore};
+
+    fn shelf(uid: Uid) -> Shelf {
+        Shelf::new(Arc::new(MemoryShelfStore::default()), uid, 1)
+    }
+
+    fn ingredient(slug: &str) -> Ingredient {
+        Ingredient::new(slug, slug)
+    }
+
+    #[test]
+    fn merge_from_another_shelf() {
+        let mine = shelf(1);
+        let theirs = mine.for_uid(2);
+        mine.add_ingredients(
+            &Bucket::Ingredients,
+            &[ingredient("egg"), ingredient("milk")],
+        )
+        .unwrap();
+        mine.add_ingredient(&Bucket::BannedIngredients, &ingredient("mushroom"))
+            .unwrap();
+        theirs
+            .add_ingredients(
+                &Bucket::Ingredients,
+                &[ingredient("egg"), ingredient("oil"), ingredient("mushroom")],
+            )
+            .unwrap();
+        theirs
+            .add_ingredient(&Bucket::KeyIngredients, &ingredient("oil"))
+            .unwrap();
+        theirs
+            .add_ingredient(&Bucket::BannedIngredients, &ingredient("milk"))
+            .unwrap();
+
+        let report = mine.merge_from(theirs.uid()).unwrap();
+
+        assert_eq!(report.added, 2);
+        assert_eq!(
+            report.conflicts,
+            vec![ingredient("milk"), ingredient("mushroom")]
+        );
+        assert_eq!(
+            mine.get_ingredients(&Bucket::Ingredients).unwrap(),
+            vec![ingredient("egg"), ingredient("milk"), ingredient("oil")]
+        );
+        assert_eq!(
+            mine.get_ingredients(&Bucket::KeyIngredients).unwrap(),
+            vec![ingredient("oil")]
+        );
+        assert_eq!(
+            mine.get_ingredients(&Bucket::BannedIngredients).unwrap(),
+            vec![ingredient("mushroom")]
+        );
+        assert_eq!(
+            theirs.get_ingredients(&Bucket::Ingredients).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn record_the_last_change_to_each_ingredient() {
+        let shelf = shelf(1);
+        let other = shelf.for_uid(2);
+        shelf
+            .add_ingredient(&Bucket::Ingredients, &ingredient("egg"))
+            .unwrap();
+        other
+            .add_ingredient(&Bucket::Ingredients, &ingredient("oil"))
+            .unwrap();
+        shelf
+            .remove_ingredient(&Bucket::Ingredients, "egg")
+            .unwrap();
+
+        let changes = shelf.changes().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].ingredient, ingredient("egg"));
+        assert!(changes[0].removed);
+
+        assert_eq!(other.changes().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undo_changes() {
+        let shelf = shelf(1);
+        shelf
+            .add_ingredients(
+                &Bucket::Ingredients,
+                &[ingredient("egg"), ingredient("oil")],
+            )
+            .unwrap();
+        shelf
+            .add_ingredient(&Bucket::BannedIngredients, &ingredient("milk"))
+            .unwrap();
+        shelf.remove_all().unwrap();
+        assert!(shelf
+            .get_ingredients(&Bucket::Ingredients)
+            .unwrap()
+            .is_empty());
+        assert_eq!(shelf.history().unwrap().len(), 3);
+
+        shelf.undo().unwrap().unwrap();
+        assert_eq!(
+            shelf.get_ingredients(&Bucket::Ingredients).unwrap(),
+            vec![ingredient("egg"), ingredient("oil")]
+        );
+        assert_eq!(
+            shelf.get_ingredients(&Bucket::BannedIngredients).unwrap(),
+            vec![ingredient("milk")]
+        );
+
+        shelf.undo().unwrap().unwrap();
+        assert!(shelf
+            .get_ingredients(&Bucket::BannedIngredients)
+            .unwrap()
+            .is_empty());
+        shelf.undo().unwrap().unwrap();
+        assert!(shelf
+            .get_ingredients(&Bucket::Ingredients)
+            .unwrap()
+            .is_empty());
+        assert!(shelf.undo().unwrap().is_none());
+    }
+
+    #[test]
+    fn undo_restores_best_before_dates() {
+        let shelf = shelf(1);
+        let date = NaiveDate::from_ymd_opt(2022, 11, 5).unwrap();
+        shelf
+            .add_ingredients(
+                &Bucket::Ingredients,
+                &[ingredient("egg"), ingredient("milk")],
+            )
+            .unwrap();
+        shelf.set_best_before("egg", Some(date)).unwrap();
+        shelf.remove_all().unwrap();
+        assert!(shelf.best_before().unwrap().is_empty());
+
+        shelf.undo().unwrap().unwrap();
+
+        assert_eq!(
+            shelf.best_before().unwrap(),
+            vec![("egg".to_string(), date)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn undo_only_reverts_the_members_own_changes() {
+        let shelf = shelf(1);
+        let other = Shelf::new(shelf.store.clone(), 1, 2);
+        shelf
+            .add_ingredient(&Bucket::Ingredients, &ingredient("egg"))
+            .unwrap();
+        other
+            .add_ingredient(&Bucket::Ingredients, &ingredient("oil"))
+            .unwrap();
+
+        shelf.undo().unwrap().unwrap();
+
+        assert_eq!(
+            shelf.get_ingredients(&Bucket::Ingredients).unwrap(),
+            vec![ingredient("oil")]
+        );
+        assert!(shelf.undo().unwrap().is_none());
+    }
+
+    #[test]
+    fn concurrent_changes_are_not_lost() {
+        for store in stores() {
+            let threads: Vec<_> = (0..8)
+                .map(|thread| {
+                    let shelf = Shelf::new(store.clone(), 1, thread);
+                    std::thread::spawn(move || {
+                        for i in 0..20 {
+                            let ingredient = ingredient(&format!("{}-{}", thread, i));
+                            assert!(shelf
+                                .add_ingredient(&Bucket::Ingredients, &ingredient)
+                                .unwrap());
+                        }
+                        for i in (0..20).step_by(2) {
+                            let slug = format!("{}-{}", thread, i);
+                            assert!(shelf
+                                .remove_ingredient(&Bucket::Ingredients, &slug)
+                                .unwrap()
+                                .is_some());
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            let shelf = Shelf::new(store, 1, 0);
+            let mut slugs = ingredient_slugs(&shelf.get_ingredients(&Bucket::Ingredients).unwrap());
+            let mut expected: Vec<_> = (0..8)
+                .flat_map(|thread| (1..20).step_by(2).map(move |i| format!("{}-{}", thread, i)))
+                .collect();
+            slugs.sort();
+            expected.sort();
+            assert_eq!(slugs, expected);
+        }
+    }
+
+    #[test]
+    fn weight_ingredients_by_best_before() {
+        let today = NaiveDate::from_ymd_opt(2022, 11, 5).unwrap();
+        let days = |days| today + chrono::Duration::days(days);
+
+        assert_eq!(use_up_weight(days(-2), today), 1.0);
+        assert_eq!(use_up_weight(today, today), 1.0);
+        assert!(use_up_weight(days(2), today) > use_up_weight(days(5), today));
+        assert_eq!(use_up_weight(days(USE_UP_DAYS), today), 0.0);
+        assert_eq!(use_up_weight(days(30), today), 0.0);
+    }
+
+    #[test]
+    fn removing_an_ingredient_clears_its_best_before_date() {
+        let shelf = shelf(1);
+        let date = NaiveDate::from_ymd_opt(2022, 11, 5).unwrap();
+        shelf
+            .add_ingredients(
+                &Bucket::Ingredients,
+                &[ingredient("egg"), ingredient("milk")],
+            )
+            .unwrap();
+        shelf.set_best_before("egg", Some(date)).unwrap();
+        shelf.set_best_before("milk", Some(date)).unwrap();
+
+        shelf
+            .remove_ingredient(&Bucket::Ingredients, "egg")
+            .unwrap();
+
+        assert_eq!(
+            shelf.best_before().unwrap(),
+            vec![("milk".to_string(), date)].into_iter().collect()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Shelf export and import as a file
+//!
+//! A shelf can be downloaded as JSON or CSV and uploaded again later, eg. after the session
+//! cookie holding the shelf uid has been lost.
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use bareshelf::{Ingredient, IngredientQuery, IngredientSlug};
+
+use crate::{
+    error::Error,
+    shelf::{Bucket, Shelf},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv",
+        }
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+
+    /// Guess the format of an uploaded file from its content
+    fn detect(data: &[u8]) -> Self {
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// All the buckets of a shelf
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ShelfFile {
+    #[serde(default)]
+    ingredients: Vec<Ingredient>,
+    #[serde(default)]
+    key_ingredients: Vec<Ingredient>,
+    #[serde(default)]
+    banned_ingredients: Vec<Ingredient>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    bucket: Bucket,
+    slug: String,
+    name: String,
+}
+
+/// The outcome of importing a shelf file
+pub(crate) struct ImportReport {
+    pub(crate) added: usize,
+    /// Ingredients in the file that are no longer in the index
+    pub(crate) missing: Vec<Ingredient>,
+}
+
+impl ShelfFile {
+    pub(crate) fn from_shelf(shelf: &Shelf) -> Result<Self, Error> {
+        let mut file = Self::default();
+        for bucket in Bucket::ALL.iter() {
+            *file.bucket_mut(bucket) = shelf.get_ingredients(bucket)?;
+        }
+        Ok(file)
+    }
+
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, Error> {
+        match Format::detect(data) {
+            Format::Json => Ok(serde_json::from_slice(data)?),
+            Format::Csv => {
+                let mut file = Self::default();
+                for row in csv::Reader::from_reader(data).deserialize() {
+                    let row: CsvRow = row?;
+                    file.bucket_mut(&row.bucket)
+                        .push(Ingredient::new(&row.name, &row.slug));
+                }
+                Ok(file)
+            }
+        }
+    }
+
+    pub(crate) fn to_bytes(&self, format: Format) -> Result<Vec<u8>, Error> {
+        match format {
+            Format::Json => Ok(serde_json::to_vec_pretty(self)?),
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(vec![]);
+                for bucket in Bucket::ALL.iter() {
+                    for ingredient in self.bucket(bucket) {
+                        writer.serialize(CsvRow {
+                            bucket: *bucket,
+                            slug: ingredient.slug.clone(),
+                            name: ingredient.name.clone(),
+                        })?;
+                    }
+                }
+                writer
+                    .into_inner()
+                    .map_err(|_| Error::Other("failed to write csv".to_string()))
+            }
+        }
+    }
+
+    /// Add every ingredient that still exists in the index to the shelf
+    pub(crate) fn import(
+        &self,
+        shelf: &Shelf,
+        searcher: &bareshelf::Searcher,
+    ) -> Result<ImportReport, Error> {
+        let slugs: Vec<IngredientSlug> = Bucket::ALL
+            .iter()
+            .flat_map(|bucket| self.bucket(bucket))
+            .map(|ingredient| IngredientSlug::from(&ingredient.slug))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let known: HashMap<String, Ingredient> = if slugs.is_empty() {
+            HashMap::new()
+        } else {
+            searcher
+                .ingredients(IngredientQuery::by_slugs(&slugs).with_limit(slugs.len()))
+                .map_err(|_| Error::Other("failed to search ingredients".to_string()))?
+                .into_iter()
+                .map(|ingredient| (ingredient.slug.clone(), ingredient))
+                .collect()
+        };
+
+        let mut report = ImportReport {
+            added: 0,
+            missing: vec![],
+        };
+        for bucket in Bucket::ALL.iter() {
+            let (found, missing): (Vec<_>, Vec<_>) = self
+                .bucket(bucket)
+                .iter()
+                .partition(|ingredient| known.contains_key(&ingredient.slug));
+            let found: Vec<_> = found
+                .into_iter()
+                .map(|ingredient| known[&ingredient.slug].clone())
+                .collect();
+            report.added += shelf.add_ingredients(bucket, &found)?;
+            report.missing.extend(missing.into_iter().cloned());
+        }
+        Ok(report)
+    }
+
+    fn bucket(&self, bucket: &Bucket) -> &[Ingredient] {
+        match bucket {
+            Bucket::KeyIngredients => &self.key_ingredients,
+            Bucket::BannedIngredients => &self.banned_ingredients,
+            Bucket::Ingredients => &self.ingredients,
+        }
+    }
+
+    fn bucket_mut(&mut self, bucket: &Bucket) -> &mut Vec<Ingredient> {
+        match bucket {
+            Bucket::KeyIngredients => &mut self.key_ingredients,
+            Bucket::BannedIngredients => &mut self.banned_ingredients,
+            Bucket::Ingredients => &mut self.ingredients,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shelf_file() -> ShelfFile {
+        ShelfFile {
+            ingredients: vec![
+                Ingredient::new("Egg", "egg"),
+                Ingredient::new("Salt, sea", "sea-salt"),
+            ],
+            key_ingredients: vec![Ingredient::new("Leek", "leek")],
+            banned_ingredients: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trip_json() {
+        let file = shelf_file();
+        let data = file.to_bytes(Format::Json).unwrap();
+
+        assert_eq!(ShelfFile::parse(&data).unwrap(), file);
+    }
+
+    #[test]
+    fn round_trip_csv() {
+        let file = shelf_file();
+        let data = file.to_bytes(Format::Csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(data.clone()).unwrap(),
+            "bucket,slug,name\nkey_ingredients,leek,Leek\ningredients,egg,Egg\ningredients,sea-salt,\"Salt, sea\"\n"
+        );
+        assert_eq!(ShelfFile::parse(&data).unwrap(), file);
+    }
+}
@@ -0,0 +1,759 @@
+//! Storage for shelves
+//!
+//! `Shelf` keeps its buckets, the last change to each ingredient and its history in a
+//! `ShelfStore`. Which store is used is chosen with the `SHELF_STORE` environment variable:
+//!
+//! - `sled` (the default) keeps shelves in the app's sled database.
+//! - `memory` keeps shelves in memory, so they are lost on restart. Useful for tests.
+//! - `sqlite` keeps shelves in the SQLite database at `SQLITE_PATH`. Requires the `sqlite`
+//!   feature, which is off by default.
+//!
+//! Only shelves move with the store. Accounts, households, share tokens, favourite, cooked and
+//! hidden recipes and shopping lists are kept in sled whichever store is used, so the sled
+//! database is still needed with `sqlite`.
+use std::{collections::HashMap, sync::Mutex};
+
+use bareshelf::Ingredient;
+use chrono::NaiveDate;
+
+use crate::{
+    error::Error,
+    history::HistoryEntry,
+    households::MemberId,
+    shelf::{Bucket, LastChange, ShelfSettings, Uid},
+};
+
+pub(crate) trait ShelfStore: Send + Sync {
+    /// The ingredients in a bucket
+    fn get(&self, uid: Uid, bucket: Bucket) -> Result<Vec<Ingredient>, Error>;
+
+    /// Replace the ingredients in a bucket if it still holds `current`
+    ///
+    /// Returns false without changing anything if the bucket holds something else.
+    fn compare_and_swap(
+        &self,
+        uid: Uid,
+        bucket: Bucket,
+        current: &[Ingredient],
+        new: &[Ingredient],
+    ) -> Result<bool, Error>;
+
+    /// Save the last change to each ingredient, replacing any earlier change to it
+    fn set_last_changes(&self, uid: Uid, changes: &[LastChange]) -> Result<(), Error>;
+
+    /// The last change to each ingredient that has been on the shelf, in no particular order
+    fn last_changes(&self, uid: Uid) -> Result<Vec<LastChange>, Error>;
+
+    fn append_history(&self, uid: Uid, entry: &HistoryEntry) -> Result<(), Error>;
+
+    /// All history entries for a shelf, newest first
+    fn history(&self, uid: Uid) -> Result<Vec<HistoryEntry>, Error>;
+
+    /// Remove and return the newest history entry a member made on a shelf
+    fn pop_history(&self, uid: Uid, member: MemberId) -> Result<Option<HistoryEntry>, Error>;
+
+    /// Drop history entries from before `before` and all but the newest `keep` entries
+    fn prune_history(&self, uid: Uid, before: u64, keep: usize) -> Result<(), Error>;
+
+    /// Best before dates of ingredients on the shelf, by slug
+    fn best_before(&self, uid: Uid) -> Result<HashMap<String, NaiveDate>, Error>;
+
+    /// Set or clear the best before date of an ingredient
+    fn set_best_before(&self, uid: Uid, slug: &str, date: Option<NaiveDate>) -> Result<(), Error>;
+
+    /// The shelf's settings, the defaults if they were never set
+    fn settings(&self, uid: Uid) -> Result<ShelfSettings, Error>;
+
+    fn set_settings(&self, uid: Uid, settings: &ShelfSettings) -> Result<(), Error>;
+}
+
+/// Stores shelves in sled
+pub(crate) struct SledShelfStore {
+    sled: sled::Db,
+    changes: sled::Tree,
+    history: sled::Tree,
+    best_before: sled::Tree,
+    settings: sled::Tree,
+}
+
+impl SledShelfStore {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            sled: sled.clone(),
+            changes: sled.open_tree("shelf_changes")?,
+            history: sled.open_tree("shelf_history")?,
+            best_before: sled.open_tree("shelf_best_before")?,
+            settings: sled.open_tree("shelf_settings")?,
+        })
+    }
+
+    fn bucket_key(uid: Uid, bucket: Bucket) -> String {
+        format!("/{}/{}", uid, bucket.session_key())
+    }
+
+    fn prefix(uid: Uid) -> Vec<u8> {
+        format!("/{}/", uid).into_bytes()
+    }
+}
+
+impl ShelfStore for SledShelfStore {
+    fn get(&self, uid: Uid, bucket: Bucket) -> Result<Vec<Ingredient>, Error> {
+        match self.sled.get(Self::bucket_key(uid, bucket))? {
+            Some(ingredients) => Ok(serde_json::from_slice(&ingredients)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        uid: Uid,
+        bucket: Bucket,
+        current: &[Ingredient],
+        new: &[Ingredient],
+    ) -> Result<bool, Error> {
+        let key = Self::bucket_key(uid, bucket);
+        // Swap against the stored bytes rather than re-serialising `current`, which may not
+        // produce the same bytes for data written by an older version
+        let stored = self.sled.get(&key)?;
+        let stored_ingredients: Vec<Ingredient> = match &stored {
+            Some(stored) => serde_json::from_slice(stored)?,
+            None => vec![],
+        };
+        if stored_ingredients != current {
+            return Ok(false);
+        }
+        Ok(self
+            .sled
+            .compare_and_swap(key, stored, Some(serde_json::to_vec(new)?))?
+            .is_ok())
+    }
+
+    fn set_last_changes(&self, uid: Uid, changes: &[LastChange]) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        for change in changes {
+            batch.insert(
+                format!(
+                    "/{}/{}/{}",
+                    uid,
+                    change.bucket.session_key(),
+                    change.ingredient.slug
+                )
+                .as_bytes(),
+                serde_json::to_vec(change)?,
+            );
+        }
+        self.changes.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn last_changes(&self, uid: Uid) -> Result<Vec<LastChange>, Error> {
+        self.changes
+            .scan_prefix(Self::prefix(uid))
+            .values()
+            .map(|change| Ok(serde_json::from_slice(&change?)?))
+            .collect()
+    }
+
+    fn append_history(&self, uid: Uid, entry: &HistoryEntry) -> Result<(), Error> {
+        let mut key = Self::prefix(uid);
+        key.extend_from_slice(&self.sled.generate_id()?.to_be_bytes());
+        self.history.insert(key, serde_json::to_vec(entry)?)?;
+        Ok(())
+    }
+
+    fn history(&self, uid: Uid) -> Result<Vec<HistoryEntry>, Error> {
+        self.history
+            .scan_prefix(Self::prefix(uid))
+            .values()
+            .rev()
+            .map(|entry| Ok(serde_json::from_slice(&entry?)?))
+            .collect()
+    }
+
+    fn pop_history(&self, uid: Uid, member: MemberId) -> Result<Option<HistoryEntry>, Error> {
+        for item in self.history.scan_prefix(Self::prefix(uid)).rev() {
+            let (key, entry) = item?;
+            let entry: HistoryEntry = serde_json::from_slice(&entry)?;
+            if entry.member == member {
+                self.history.remove(key)?;
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    fn prune_history(&self, uid: Uid, before: u64, keep: usize) -> Result<(), Error> {
+        for (position, item) in self
+            .history
+            .scan_prefix(Self::prefix(uid))
+            .rev()
+            .enumerate()
+        {
+            let (key, entry) = item?;
+            let entry: HistoryEntry = serde_json::from_slice(&entry)?;
+            if position >= keep || entry.at < before {
+                self.history.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn best_before(&self, uid: Uid) -> Result<HashMap<String, NaiveDate>, Error> {
+        let prefix = Self::prefix(uid);
+        self.best_before
+            .scan_prefix(&prefix)
+            .map(|item| {
+                let (key, date) = item?;
+                Ok((
+                    String::from_utf8_lossy(&key[prefix.len()..]).into_owned(),
+                    serde_json::from_slice(&date)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn set_best_before(&self, uid: Uid, slug: &str, date: Option<NaiveDate>) -> Result<(), Error> {
+        let key = format!("/{}/{}", uid, slug);
+        match date {
+            Some(date) => self.best_before.insert(key, serde_json::to_vec(&date)?)?,
+            None => self.best_before.remove(key)?,
+        };
+        Ok(())
+    }
+
+    fn settings(&self, uid: Uid) -> Result<ShelfSettings, Error> {
+        match self.settings.get(uid.to_be_bytes())? {
+            Some(settings) => Ok(serde_json::from_slice(&settings)?),
+            None => Ok(ShelfSettings::default()),
+        }
+    }
+
+    fn set_settings(&self, uid: Uid, settings: &ShelfSettings) -> Result<(), Error> {
+        self.settings
+            .insert(uid.to_be_bytes(), serde_json::to_vec(settings)?)?;
+        Ok(())
+    }
+}
+
+/// Stores shelves in memory
+#[derive(Default)]
+pub(crate) struct MemoryShelfStore {
+    buckets: Mutex<HashMap<(Uid, Bucket), Vec<Ingredient>>>,
+    changes: Mutex<HashMap<(Uid, Bucket, String), LastChange>>,
+    /// Oldest first
+    history: Mutex<HashMap<Uid, Vec<HistoryEntry>>>,
+    best_before: Mutex<HashMap<(Uid, String), NaiveDate>>,
+    settings: Mutex<HashMap<Uid, ShelfSettings>>,
+}
+
+impl ShelfStore for MemoryShelfStore {
+    fn get(&self, uid: Uid, bucket: Bucket) -> Result<Vec<Ingredient>, Error> {
+        let buckets = self.buckets.lock().unwrap();
+        Ok(buckets.get(&(uid, bucket)).cloned().unwrap_or_default())
+    }
+
+    fn compare_and_swap(
+        &self,
+        uid: Uid,
+        bucket: Bucket,
+        current: &[Ingredient],
+        new: &[Ingredient],
+    ) -> Result<bool, Error> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let ingredients = buckets.entry((uid, bucket)).or_default();
+        if ingredients.as_slice() != current {
+            return Ok(false);
+        }
+        *ingredients = new.to_vec();
+        Ok(true)
+    }
+
+    fn set_last_changes(&self, uid: Uid, changes: &[LastChange]) -> Result<(), Error> {
+        let mut last_changes = self.changes.lock().unwrap();
+        for change in changes {
+            last_changes.insert(
+                (uid, change.bucket, change.ingredient.slug.clone()),
+                change.clone(),
+            );
+        }
+        Ok(())
+    }
+
+    fn last_changes(&self, uid: Uid) -> Result<Vec<LastChange>, Error> {
+        let changes = self.changes.lock().unwrap();
+        Ok(changes
+            .iter()
+            .filter(|((change_uid, _, _), _)| *change_uid == uid)
+            .map(|(_, change)| change.clone())
+            .collect())
+    }
+
+    fn append_history(&self, uid: Uid, entry: &HistoryEntry) -> Result<(), Error> {
+        let mut history = self.history.lock().unwrap();
+        history.entry(uid).or_default().push(entry.clone());
+        Ok(())
+    }
+
+    fn history(&self, uid: Uid) -> Result<Vec<HistoryEntry>, Error> {
+        let history = self.history.lock().unwrap();
+        Ok(history
+            .get(&uid)
+            .map(|entries| entries.iter().rev().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn pop_history(&self, uid: Uid, member: MemberId) -> Result<Option<HistoryEntry>, Error> {
+        let mut history = self.history.lock().unwrap();
+        Ok(history.get_mut(&uid).and_then(|entries| {
+            let position = entries.iter().rposition(|entry| entry.member == member)?;
+            Some(entries.remove(position))
+        }))
+    }
+
+    fn prune_history(&self, uid: Uid, before: u64, keep: usize) -> Result<(), Error> {
+        let mut history = self.history.lock().unwrap();
+        if let Some(entries) = history.get_mut(&uid) {
+            entries.drain(..entries.len().saturating_sub(keep));
+            entries.retain(|entry| entry.at >= before);
+        }
+        Ok(())
+    }
+
+    fn best_before(&self, uid: Uid) -> Result<HashMap<String, NaiveDate>, Error> {
+        let best_before = self.best_before.lock().unwrap();
+        Ok(best_before
+            .iter()
+            .filter(|((date_uid, _), _)| *date_uid == uid)
+            .map(|((_, slug), date)| (slug.clone(), *date))
+            .collect())
+    }
+
+    fn set_best_before(&self, uid: Uid, slug: &str, date: Option<NaiveDate>) -> Result<(), Error> {
+        let mut best_before = self.best_before.lock().unwrap();
+        match date {
+            Some(date) => best_before.insert((uid, slug.to_string()), date),
+            None => best_before.remove(&(uid, slug.to_string())),
+        };
+        Ok(())
+    }
+
+    fn settings(&self, uid: Uid) -> Result<ShelfSettings, Error> {
+        let settings = self.settings.lock().unwrap();
+        Ok(settings.get(&uid).cloned().unwrap_or_default())
+    }
+
+    fn set_settings(&self, uid: Uid, settings: &ShelfSettings) -> Result<(), Error> {
+        let mut all_settings = self.settings.lock().unwrap();
+        all_settings.insert(uid, settings.clone());
+        Ok(())
+    }
+}
+
+/// Stores shelves in SQLite
+#[cfg(feature = "sqlite")]
+pub(crate) struct SqliteShelfStore {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteShelfStore {
+    pub(crate) fn open(path: &std::path::Path) -> Result<Self, Error> {
+        Self::new(rusqlite::Connection::open(path)?)
+    }
+
+    pub(crate) fn new(connection: rusqlite::Connection) -> Result<Self, Error> {
+        connection.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS shelf_buckets (
+                uid TEXT NOT NULL,
+                bucket TEXT NOT NULL,
+                ingredients TEXT NOT NULL,
+                PRIMARY KEY (uid, bucket)
+            );
+            CREATE TABLE IF NOT EXISTS shelf_changes (
+                uid TEXT NOT NULL,
+                bucket TEXT NOT NULL,
+                slug TEXT NOT NULL,
+                change TEXT NOT NULL,
+                PRIMARY KEY (uid, bucket, slug)
+            );
+            CREATE TABLE IF NOT EXISTS shelf_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                uid TEXT NOT NULL,
+                at INTEGER NOT NULL,
+                entry TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS shelf_history_uid ON shelf_history (uid, id);
+            CREATE TABLE IF NOT EXISTS shelf_best_before (
+                uid TEXT NOT NULL,
+                slug TEXT NOT NULL,
+                best_before TEXT NOT NULL,
+                PRIMARY KEY (uid, slug)
+            );
+            CREATE TABLE IF NOT EXISTS shelf_settings (
+                uid TEXT PRIMARY KEY,
+                settings TEXT NOT NULL
+            );
+            ",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn get_bucket(
+        connection: &rusqlite::Connection,
+        uid: Uid,
+        bucket: Bucket,
+    ) -> Result<Vec<Ingredient>, Error> {
+        use rusqlite::OptionalExtension;
+
+        let ingredients: Option<String> = connection
+            .query_row(
+                "SELECT ingredients FROM shelf_buckets WHERE uid = ?1 AND bucket = ?2",
+                rusqlite::params![uid.to_string(), bucket.session_key()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match ingredients {
+            Some(ingredients) => Ok(serde_json::from_str(&ingredients)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn history_entries(
+        connection: &rusqlite::Connection,
+        uid: Uid,
+    ) -> Result<Vec<(i64, HistoryEntry)>, Error> {
+        let mut statement = connection
+            .prepare("SELECT id, entry FROM shelf_history WHERE uid = ?1 ORDER BY id DESC")?;
+        let rows = statement.query_map([uid.to_string()], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (id, entry) = row?;
+            Ok((id, serde_json::from_str(&entry)?))
+        })
+        .collect()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ShelfStore for SqliteShelfStore {
+    fn get(&self, uid: Uid, bucket: Bucket) -> Result<Vec<Ingredient>, Error> {
+        Self::get_bucket(&self.connection.lock().unwrap(), uid, bucket)
+    }
+
+    fn compare_and_swap(
+        &self,
+        uid: Uid,
+        bucket: Bucket,
+        current: &[Ingredient],
+        new: &[Ingredient],
+    ) -> Result<bool, Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        if Self::get_bucket(&transaction, uid, bucket)? != current {
+            return Ok(false);
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO shelf_buckets (uid, bucket, ingredients) VALUES (?1, ?2, ?3)",
+            rusqlite::params![
+                uid.to_string(),
+                bucket.session_key(),
+                serde_json::to_string(new)?
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    fn set_last_changes(&self, uid: Uid, changes: &[LastChange]) -> Result<(), Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for change in changes {
+            transaction.execute(
+                "INSERT OR REPLACE INTO shelf_changes (uid, bucket, slug, change)
+                VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![
+                    uid.to_string(),
+                    change.bucket.session_key(),
+                    change.ingredient.slug,
+                    serde_json::to_string(change)?
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn last_changes(&self, uid: Uid) -> Result<Vec<LastChange>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT change FROM shelf_changes WHERE uid = ?1")?;
+        let rows = statement.query_map([uid.to_string()], |row| row.get::<_, String>(0))?;
+        rows.map(|change| Ok(serde_json::from_str(&change?)?))
+            .collect()
+    }
+
+    fn append_history(&self, uid: Uid, entry: &HistoryEntry) -> Result<(), Error> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO shelf_history (uid, at, entry) VALUES (?1, ?2, ?3)",
+            rusqlite::params![
+                uid.to_string(),
+                entry.at as i64,
+                serde_json::to_string(entry)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn history(&self, uid: Uid) -> Result<Vec<HistoryEntry>, Error> {
+        let connection = self.connection.lock().unwrap();
+        Ok(Self::history_entries(&connection, uid)?
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    fn pop_history(&self, uid: Uid, member: MemberId) -> Result<Option<HistoryEntry>, Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let newest = Self::history_entries(&transaction, uid)?
+            .into_iter()
+            .find(|(_, entry)| entry.member == member);
+        if let Some((id, _)) = newest {
+            transaction.execute("DELETE FROM shelf_history WHERE id = ?1", [id])?;
+        }
+        transaction.commit()?;
+        Ok(newest.map(|(_, entry)| entry))
+    }
+
+    fn prune_history(&self, uid: Uid, before: u64, keep: usize) -> Result<(), Error> {
+        self.connection.lock().unwrap().execute(
+            "DELETE FROM shelf_history WHERE uid = ?1 AND (at < ?2 OR id NOT IN (
+                SELECT id FROM shelf_history WHERE uid = ?1 ORDER BY id DESC LIMIT ?3
+            ))",
+            rusqlite::params![uid.to_string(), before as i64, keep as i64],
+        )?;
+        Ok(())
+    }
+
+    fn best_before(&self, uid: Uid) -> Result<HashMap<String, NaiveDate>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT slug, best_before FROM shelf_best_before WHERE uid = ?1")?;
+        let rows = statement.query_map([uid.to_string()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (slug, date) = row?;
+            let date = date
+                .parse()
+                .map_err(|_| Error::Other(format!("invalid best before date {}", date)))?;
+            Ok((slug, date))
+        })
+        .collect()
+    }
+
+    fn set_best_before(&self, uid: Uid, slug: &str, date: Option<NaiveDate>) -> Result<(), Error> {
+        let connection = self.connection.lock().unwrap();
+        match date {
+            Some(date) => connection.execute(
+                "INSERT OR REPLACE INTO shelf_best_before (uid, slug, best_before)
+                VALUES (?1, ?2, ?3)",
+                rusqlite::params![uid.to_string(), slug, date.to_string()],
+            )?,
+            None => connection.execute(
+                "DELETE FROM shelf_best_before WHERE uid = ?1 AND slug = ?2",
+                rusqlite::params![uid.to_string(), slug],
+            )?,
+        };
+        Ok(())
+    }
+
+    fn settings(&self, uid: Uid) -> Result<ShelfSettings, Error> {
+        use rusqlite::OptionalExtension;
+
+        let connection = self.connection.lock().unwrap();
+        let settings: Option<String> = connection
+            .query_row(
+                "SELECT settings FROM shelf_settings WHERE uid = ?1",
+                [uid.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match settings {
+            Some(settings) => Ok(serde_json::from_str(&settings)?),
+            None => Ok(ShelfSettings::default()),
+        }
+    }
+
+    fn set_settings(&self, uid: Uid, settings: &ShelfSettings) -> Result<(), Error> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO shelf_settings (uid, settings) VALUES (?1, ?2)",
+            rusqlite::params![uid.to_string(), serde_json::to_string(settings)?],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{diets::Diet, history::BucketChange};
+
+    /// One of each store, for running the same test against all of them
+    pub(crate) fn stores() -> Vec<Arc<dyn ShelfStore>> {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        vec![
+            Arc::new(SledShelfStore::new(&sled).unwrap()),
+            Arc::new(MemoryShelfStore::default()),
+            #[cfg(feature = "sqlite")]
+            Arc::new(
+                SqliteShelfStore::new(rusqlite::Connection::open_in_memory().unwrap()).unwrap(),
+            ),
+        ]
+    }
+
+    fn ingredient(slug: &str) -> Ingredient {
+        Ingredient::new(slug, slug)
+    }
+
+    fn entry(at: u64, slug: &str) -> HistoryEntry {
+        HistoryEntry {
+            member: 1,
+            at,
+            changes: vec![BucketChange::added(
+                Bucket::Ingredients,
+                vec![ingredient(slug)],
+            )],
+        }
+    }
+
+    #[test]
+    fn compare_and_swap_buckets() {
+        for store in stores() {
+            let egg = [ingredient("egg")];
+
+            assert!(store.get(1, Bucket::Ingredients).unwrap().is_empty());
+            assert!(!store
+                .compare_and_swap(1, Bucket::Ingredients, &egg, &[])
+                .unwrap());
+            assert!(store
+                .compare_and_swap(1, Bucket::Ingredients, &[], &egg)
+                .unwrap());
+            assert!(!store
+                .compare_and_swap(1, Bucket::Ingredients, &[], &egg)
+                .unwrap());
+
+            assert_eq!(store.get(1, Bucket::Ingredients).unwrap(), egg);
+            assert!(store.get(1, Bucket::KeyIngredients).unwrap().is_empty());
+            assert!(store.get(12, Bucket::Ingredients).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn replace_last_changes() {
+        for store in stores() {
+            let change = |slug: &str, removed| LastChange {
+                member: 1,
+                bucket: Bucket::Ingredients,
+                ingredient: ingredient(slug),
+                removed,
+                at: 100,
+            };
+            store
+                .set_last_changes(1, &[change("egg", false), change("oil", false)])
+                .unwrap();
+            store.set_last_changes(1, &[change("egg", true)]).unwrap();
+
+            let mut changes = store.last_changes(1).unwrap();
+            changes.sort_by(|a, b| a.ingredient.cmp(&b.ingredient));
+            assert_eq!(changes.len(), 2);
+            assert!(changes[0].removed);
+            assert!(!changes[1].removed);
+            assert!(store.last_changes(12).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn append_and_pop_history() {
+        for store in stores() {
+            let other_member = HistoryEntry {
+                member: 2,
+                ..entry(102, "salt")
+            };
+            store.append_history(1, &entry(100, "egg")).unwrap();
+            store.append_history(1, &entry(101, "oil")).unwrap();
+            store.append_history(1, &other_member).unwrap();
+            store.append_history(12, &entry(102, "milk")).unwrap();
+
+            assert_eq!(
+                store.history(1).unwrap(),
+                vec![other_member.clone(), entry(101, "oil"), entry(100, "egg")]
+            );
+            assert_eq!(store.pop_history(1, 1).unwrap(), Some(entry(101, "oil")));
+            assert_eq!(store.pop_history(1, 1).unwrap(), Some(entry(100, "egg")));
+            assert_eq!(store.pop_history(1, 1).unwrap(), None);
+            assert_eq!(store.history(1).unwrap(), vec![other_member]);
+            assert_eq!(store.history(12).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn prune_history_by_age_and_size() {
+        for store in stores() {
+            for at in 0..5 {
+                store.append_history(1, &entry(at, "egg")).unwrap();
+            }
+            store.append_history(12, &entry(0, "egg")).unwrap();
+
+            store.prune_history(1, 0, 3).unwrap();
+
+            let at: Vec<_> = store.history(1).unwrap().iter().map(|e| e.at).collect();
+            assert_eq!(at, vec![4, 3, 2]);
+
+            store.prune_history(1, 3, 3).unwrap();
+
+            let at: Vec<_> = store.history(1).unwrap().iter().map(|e| e.at).collect();
+            assert_eq!(at, vec![4, 3]);
+            assert_eq!(store.history(12).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn set_and_clear_best_before_dates() {
+        for store in stores() {
+            let date = NaiveDate::from_ymd_opt(2022, 11, 5).unwrap();
+            store.set_best_before(1, "egg", Some(date)).unwrap();
+            store.set_best_before(1, "milk", Some(date)).unwrap();
+            store.set_best_before(12, "oil", Some(date)).unwrap();
+            store.set_best_before(1, "milk", None).unwrap();
+
+            assert_eq!(
+                store.best_before(1).unwrap(),
+                vec![("egg".to_string(), date)].into_iter().collect()
+            );
+            assert_eq!(store.best_before(12).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn save_settings() {
+        for store in stores() {
+            let settings = ShelfSettings {
+                diets: vec![Diet::Vegan, Diet::NutFree],
+                allergens: vec![bareshelf::Allergen::Sesame],
+                key_ingredient_mode: bareshelf::KeyIngredientMode::AtLeast(2),
+            };
+            store.set_settings(1, &settings).unwrap();
+
+            assert_eq!(store.settings(1).unwrap(), settings);
+            assert_eq!(store.settings(12).unwrap(), ShelfSettings::default());
+        }
+    }
+}
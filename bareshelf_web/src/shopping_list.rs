@@ -0,0 +1,189 @@
+//! Shopping lists
+//!
+//! Each shelf has a list of ingredients to buy, usually the missing ingredients of recipes the
+//! user wants to make. An ingredient is listed once however many recipes need it, along with the
+//! recipes it is for. Recipes don't record quantities so the number of recipes stands in for one.
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use serde::{Deserialize, Serialize};
+
+use bareshelf::Ingredient;
+
+use crate::{error::Error, shelf::Uid};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ShoppingItem {
+    pub(crate) ingredient: Ingredient,
+    /// Titles of the recipes that need the ingredient
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) recipes: Vec<String>,
+}
+
+impl ShoppingItem {
+    /// How many to buy, one for each recipe or one if it was added on its own
+    pub(crate) fn quantity(&self) -> usize {
+        self.recipes.len().max(1)
+    }
+}
+
+pub(crate) struct ShoppingLists {
+    tree: sled::Tree,
+}
+
+impl ShoppingLists {
+    pub(crate) fn new(sled: &sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            tree: sled.open_tree("shopping_lists")?,
+        })
+    }
+
+    /// The shopping list of a shelf in the order items were added
+    pub(crate) fn get(&self, uid: Uid) -> Result<Vec<ShoppingItem>, Error> {
+        match self.tree.get(uid.to_be_bytes())? {
+            Some(items) => Ok(serde_json::from_slice(&items)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Add ingredients to a shopping list, optionally noting the recipe they are for
+    ///
+    /// Returns how many ingredients were not already on the list.
+    pub(crate) fn add(
+        &self,
+        uid: Uid,
+        recipe: Option<&str>,
+        ingredients: &[Ingredient],
+    ) -> Result<usize, Error> {
+        self.update(uid, |items| {
+            let mut added = 0;
+            for ingredient in ingredients {
+                let item = match items.iter().position(|item| item.ingredient == *ingredient) {
+                    Some(position) => &mut items[position],
+                    None => {
+                        added += 1;
+                        items.push(ShoppingItem {
+                            ingredient: ingredient.clone(),
+                            recipes: vec![],
+                        });
+                        items.last_mut().unwrap()
+                    }
+                };
+                if let Some(recipe) = recipe {
+                    if !item.recipes.iter().any(|r| r == recipe) {
+                        item.recipes.push(recipe.to_string());
+                    }
+                }
+            }
+            added
+        })
+    }
+
+    /// Take items off a shopping list, or every item if `slug` is `None`
+    pub(crate) fn remove(&self, uid: Uid, slug: Option<&str>) -> Result<Vec<ShoppingItem>, Error> {
+        self.update(uid, |items| {
+            let (removed, kept) = std::mem::take(items)
+                .into_iter()
+                .partition(|item| slug.map_or(true, |slug| item.ingredient.slug == slug));
+            *items = kept;
+            removed
+        })
+    }
+
+    /// Change a shopping list, retrying if it was changed by another request in the meantime
+    fn update<T>(
+        &self,
+        uid: Uid,
+        mut change: impl FnMut(&mut Vec<ShoppingItem>) -> T,
+    ) -> Result<T, Error> {
+        let key = uid.to_be_bytes();
+        loop {
+            let current = self.tree.get(key)?;
+            let mut items: Vec<ShoppingItem> = match &current {
+                Some(items) => serde_json::from_slice(items)?,
+                None => vec![],
+            };
+            let result = change(&mut items);
+            let new = if items.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_vec(&items)?)
+            };
+            if self.tree.compare_and_swap(key, current, new)?.is_ok() {
+                return Ok(result);
+            }
+        }
+    }
+}
+
+impl FromRequest for ShoppingLists {
+    type Error = Error;
+    type Future = Ready<Result<ShoppingLists, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let sled = req.app_data::<web::Data<sled::Db>>().unwrap();
+        ready(ShoppingLists::new(sled.get_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shopping_lists() -> ShoppingLists {
+        let sled = sled::Config::new().temporary(true).open().unwrap();
+        ShoppingLists::new(&sled).unwrap()
+    }
+
+    fn ingredient(slug: &str) -> Ingredient {
+        Ingredient::new(slug, slug)
+    }
+
+    #[test]
+    fn ingredients_are_listed_once_per_recipe() {
+        let lists = shopping_lists();
+
+        let added = lists
+            .add(
+                1,
+                Some("Omelette"),
+                &[ingredient("egg"), ingredient("milk")],
+            )
+            .unwrap();
+        assert_eq!(added, 2);
+        let added = lists
+            .add(1, Some("Fried egg"), &[ingredient("egg")])
+            .unwrap();
+        assert_eq!(added, 0);
+        lists
+            .add(1, Some("Fried egg"), &[ingredient("egg")])
+            .unwrap();
+        lists.add(1, None, &[ingredient("salt")]).unwrap();
+
+        let items = lists.get(1).unwrap();
+        let quantities: Vec<_> = items
+            .iter()
+            .map(|item| (item.ingredient.slug.as_str(), item.quantity()))
+            .collect();
+        assert_eq!(quantities, vec![("egg", 2), ("milk", 1), ("salt", 1)]);
+        assert!(lists.get(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn remove_items() {
+        let lists = shopping_lists();
+        lists
+            .add(
+                1,
+                None,
+                &[ingredient("egg"), ingredient("milk"), ingredient("salt")],
+            )
+            .unwrap();
+
+        let removed = lists.remove(1, Some("milk")).unwrap();
+        assert_eq!(removed[0].ingredient, ingredient("milk"));
+        assert!(lists.remove(1, Some("milk")).unwrap().is_empty());
+
+        assert_eq!(lists.remove(1, None).unwrap().len(), 2);
+        assert!(lists.get(1).unwrap().is_empty());
+    }
+}
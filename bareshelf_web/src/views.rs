@@ -8,14 +8,46 @@ use serde::Serialize;
 pub struct RecipeSearchResult {
     score: f32,
     title: String,
+    slug: String,
     url: String,
     source: String,
     chef_name: Option<String>,
     image_name: Option<String>,
     ingredients: Vec<RecipeSearchResultIngredient>,
     num_missing: usize,
+    favourite: bool,
+    /// Names of the diets the recipe keeps to
+    diets: Vec<&'static str>,
+    /// Names of the allergens the recipe contains
+    allergens: Vec<&'static str>,
 }
 
+impl RecipeSearchResult {
+    /// Mark the recipe as a favourite if it is one of these
+    pub(crate) fn with_favourites(
+        mut self,
+        favourites: &[crate::recipe_lists::SavedRecipe],
+    ) -> Self {
+        self.favourite = favourites
+            .iter()
+            .any(|favourite| favourite.slug == self.slug);
+        self
+    }
+
+    /// Note which diets the recipe keeps to
+    pub(crate) fn with_diets(mut self, diet_bans: &crate::diets::DietBans) -> Self {
+        self.diets = diet_bans
+            .kept_by(
+                self.ingredients
+                    .iter()
+                    .map(|ingredient| ingredient.slug.as_str()),
+            )
+            .iter()
+            .map(|diet| diet.name())
+            .collect();
+        self
+    }
+}
 
 impl From<&bareshelf::RecipeSearchResult> for RecipeSearchResult {
     fn from(recipe: &bareshelf::RecipeSearchResult) -> Self {
@@ -23,12 +55,9 @@ impl From<&bareshelf::RecipeSearchResult> for RecipeSearchResult {
         Self {
             score: recipe.score,
             title: recipe.recipe.title.clone(),
+            slug: recipe.recipe.slug.clone(),
             url: recipe.recipe.url.clone(),
-            source: url::Url::parse(&recipe.recipe.url)
-                .unwrap()
-                .host_str()
-                .unwrap()
-                .to_owned(),
+            source: recipe.recipe.host().unwrap_or_default(),
             chef_name: recipe.recipe.chef_name.clone(),
             image_name: recipe.recipe.image_name.clone(),
             ingredients: recipe
@@ -42,6 +71,14 @@ impl From<&bareshelf::RecipeSearchResult> for RecipeSearchResult {
                 })
                 .collect(),
             num_missing: missing.len(),
+            favourite: false,
+            diets: vec![],
+            allergens: recipe
+                .recipe
+                .allergens
+                .iter()
+                .map(|allergen| allergen.name())
+                .collect(),
         }
     }
 }
@@ -58,3 +95,300 @@ pub struct RecipeSearchResultIngredient {
     slug: String,
     is_missing: bool,
 }
+
+/// A diet or allergen the user can choose for their shelf
+#[derive(Serialize)]
+pub struct SettingChoice {
+    slug: &'static str,
+    name: &'static str,
+    chosen: bool,
+}
+
+impl SettingChoice {
+    pub(crate) fn new(slug: &'static str, name: &'static str, chosen: bool) -> Self {
+        Self { slug, name, chosen }
+    }
+}
+
+/// A shelf bucket and whether an ingredient is in it
+#[derive(Serialize)]
+pub struct IngredientBucket {
+    bucket: crate::shelf::Bucket,
+    name: String,
+    contains: bool,
+}
+
+impl IngredientBucket {
+    pub(crate) fn new(bucket: crate::shelf::Bucket, contains: bool) -> Self {
+        Self {
+            bucket,
+            name: bucket.flash_name(),
+            contains,
+        }
+    }
+}
+
+/// An ingredient that goes well with another
+#[derive(Serialize)]
+pub struct Pairing {
+    name: String,
+    slug: String,
+    /// Number of recipes using both ingredients
+    recipes: u64,
+    on_shelf: bool,
+}
+
+impl Pairing {
+    pub(crate) fn new(pairing: &bareshelf::IngredientPairing, on_shelf: bool) -> Self {
+        Self {
+            name: pairing.ingredient.name.clone(),
+            slug: pairing.ingredient.slug.clone(),
+            recipes: pairing.recipes,
+            on_shelf,
+        }
+    }
+}
+
+/// A site, chef or ingredient the recipe listing can be narrowed to
+#[derive(Serialize)]
+pub struct RefineChoice {
+    value: String,
+    name: String,
+    /// How many of the listed recipes it would leave
+    count: u64,
+}
+
+impl RefineChoice {
+    pub(crate) fn new(value: String, name: String, count: u64) -> Self {
+        Self { value, name, count }
+    }
+}
+
+/// A line from a pasted list and what it resolved to
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ImportLine {
+    Matched {
+        line: String,
+        ingredient: bareshelf::Ingredient,
+        existing: bool,
+    },
+    Ambiguous {
+        line: String,
+        candidates: Vec<bareshelf::Ingredient>,
+    },
+    Unmatched {
+        line: String,
+    },
+}
+
+/// A share link the user has created
+#[derive(Serialize)]
+pub struct SharedLink {
+    id: u64,
+    permission: crate::sharing::Permission,
+    url: String,
+    expires_in_days: u64,
+}
+
+impl SharedLink {
+    pub(crate) fn new(token: &crate::sharing::ShareToken, url: String, now: u64) -> Self {
+        Self {
+            id: token.id,
+            permission: token.permission,
+            url,
+            expires_in_days: token.expires.saturating_sub(now) / (24 * 60 * 60),
+        }
+    }
+}
+
+/// An ingredient on a shared shelf and who last changed it
+#[derive(Serialize)]
+pub struct ShelfIngredient {
+    #[serde(flatten)]
+    ingredient: bareshelf::Ingredient,
+    changed_by: Option<String>,
+}
+
+impl ShelfIngredient {
+    pub(crate) fn new(ingredient: bareshelf::Ingredient, changed_by: Option<String>) -> Self {
+        Self {
+            ingredient,
+            changed_by,
+        }
+    }
+}
+
+/// An ingredient on the shelf and when it should be used by
+#[derive(Serialize)]
+pub struct ExpiringIngredient {
+    name: String,
+    slug: String,
+    best_before: Option<String>,
+    days_left: Option<i64>,
+    /// Whether it is within `shelf::USE_UP_DAYS` of its best before date
+    soon: bool,
+}
+
+impl ExpiringIngredient {
+    pub(crate) fn new(
+        ingredient: bareshelf::Ingredient,
+        best_before: Option<chrono::NaiveDate>,
+        today: chrono::NaiveDate,
+    ) -> Self {
+        let days_left = best_before.map(|date| (date - today).num_days());
+        Self {
+            name: ingredient.name,
+            slug: ingredient.slug,
+            best_before: best_before.map(|date| date.format("%Y-%m-%d").to_string()),
+            days_left,
+            soon: days_left.is_some_and(|days| days < crate::shelf::USE_UP_DAYS),
+        }
+    }
+}
+
+/// An ingredient on the shopping list
+#[derive(Serialize)]
+pub struct ShoppingListItem {
+    name: String,
+    slug: String,
+    quantity: usize,
+    recipes: Vec<String>,
+}
+
+impl From<crate::shopping_list::ShoppingItem> for ShoppingListItem {
+    fn from(item: crate::shopping_list::ShoppingItem) -> Self {
+        Self {
+            quantity: item.quantity(),
+            name: item.ingredient.name,
+            slug: item.ingredient.slug,
+            recipes: item.recipes,
+        }
+    }
+}
+
+/// A recent change to a shared shelf
+#[derive(Serialize)]
+pub struct ShelfChange {
+    who: String,
+    ingredient: String,
+    bucket: String,
+    removed: bool,
+}
+
+impl ShelfChange {
+    pub(crate) fn new(
+        change: &crate::shelf::LastChange,
+        household: &crate::households::Household,
+    ) -> Self {
+        Self {
+            who: household.name_of(change.member).to_string(),
+            ingredient: change.ingredient.name.clone(),
+            bucket: change.bucket.flash_name(),
+            removed: change.removed,
+        }
+    }
+}
+
+/// A member of the household using a shelf
+#[derive(Serialize)]
+pub struct HouseholdMember {
+    name: String,
+    is_owner: bool,
+    is_you: bool,
+}
+
+impl HouseholdMember {
+    pub(crate) fn all(
+        household: &crate::households::Household,
+        you: crate::households::MemberId,
+    ) -> Vec<Self> {
+        household
+            .members
+            .iter()
+            .map(|member| Self {
+                name: member.name.clone(),
+                is_owner: member.id == household.owner,
+                is_you: member.id == you,
+            })
+            .collect()
+    }
+}
+
+/// A change in a shelf's history
+#[derive(Serialize)]
+pub struct HistoryItem {
+    who: String,
+    when: String,
+    description: String,
+}
+
+impl HistoryItem {
+    pub(crate) fn new(entry: &crate::history::HistoryEntry, who: String, now: u64) -> Self {
+        Self {
+            who,
+            when: ago(entry.at, now),
+            description: describe_changes(&entry.changes),
+        }
+    }
+}
+
+/// A recipe that was cooked from the shelf
+#[derive(Serialize)]
+pub struct CookedRecipe {
+    title: String,
+    when: String,
+}
+
+impl CookedRecipe {
+    pub(crate) fn new(cooked: &crate::recipe_lists::Cooked, now: u64) -> Self {
+        Self {
+            title: cooked.title.clone(),
+            when: ago(cooked.at, now),
+        }
+    }
+}
+
+/// Describe changes as e.g. "added Egg, Oil to shelf; removed Milk from banned ingredients"
+pub(crate) fn describe_changes(changes: &[crate::history::BucketChange]) -> String {
+    let names = |ingredients: &[bareshelf::Ingredient]| {
+        ingredients
+            .iter()
+            .map(|ingredient| ingredient.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut descriptions = vec![];
+    for change in changes {
+        if !change.added.is_empty() {
+            descriptions.push(format!(
+                "added {} to {}",
+                names(&change.added),
+                change.bucket.flash_name()
+            ));
+        }
+        if !change.removed.is_empty() {
+            descriptions.push(format!(
+                "removed {} from {}",
+                names(&change.removed),
+                change.bucket.flash_name()
+            ));
+        }
+    }
+    descriptions.join("; ")
+}
+
+fn ago(at: u64, now: u64) -> String {
+    let (count, unit) = match now.saturating_sub(at) {
+        seconds if seconds < 60 => return "just now".to_string(),
+        seconds if seconds < 60 * 60 => (seconds / 60, "minute"),
+        seconds if seconds < 24 * 60 * 60 => (seconds / (60 * 60), "hour"),
+        seconds => (seconds / (24 * 60 * 60), "day"),
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}